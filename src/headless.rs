@@ -0,0 +1,759 @@
+use std::{
+    io::{self, BufRead, Read, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    object::Transform,
+    path_tracer::{Checkpoint, PathTracer, TracerMode},
+    scene::{
+        CameraAnimation, FrameBudget, FrameData, PixelFilter, Progress, Projection, RenderParam,
+        SamplerKind, Scene, Shutter, StopCriteria, Texture,
+    },
+};
+
+/// Settings of an offline render, see [`HeadlessOptions::from_args`].
+#[derive(Clone, Debug)]
+pub struct HeadlessOptions {
+    /// One of [`Scene::NAMES`].
+    pub scene: String,
+    pub size: (u32, u32),
+    /// Samples per pixel after which the render stops.
+    pub samples: u32,
+    pub samples_per_frame: u32,
+    pub sampler: SamplerKind,
+    pub filter: PixelFilter,
+    /// Replaces the usual radius of `filter`, in pixels.
+    pub filter_radius: Option<f32>,
+    /// See [`RenderParam::seed`].
+    pub seed: u32,
+    /// See [`RenderParam::clamp_direct`], zero disables it like the other suppressions.
+    pub clamp_direct: f32,
+    pub clamp_indirect: f32,
+    pub outlier_sigma: f32,
+    /// Rendering time after which the render stops.
+    pub max_time: Option<Duration>,
+    /// Stops once every pixel is below this relative error.
+    pub noise_threshold: Option<f32>,
+    /// Spends the samples on the noisiest pixels, see [`RenderParam::adaptive`].
+    pub adaptive: bool,
+    pub mode: TracerMode,
+    /// Name of the bookmark of the scene to render from instead of its camera.
+    pub bookmark: Option<String>,
+    /// Replaces the perspective of the scene camera.
+    pub projection: Option<Projection>,
+    /// Replaces the aperture diameter of the scene camera.
+    pub aperture: Option<f32>,
+    /// Replace the fields of the aperture shape of the scene camera, see
+    /// [`Aperture`](crate::scene::Aperture).
+    pub aperture_blades: Option<u32>,
+    pub aperture_rotation: Option<f32>,
+    pub anamorphic: Option<f32>,
+    /// PFM image of the lens opening, see [`Scene::aperture_mask`].
+    pub aperture_mask: Option<PathBuf>,
+    /// Replaces the shutter interval of the scene camera.
+    pub shutter: Option<Shutter>,
+    /// Replaces the motion of the scene camera by a move of this distance over the frame.
+    pub camera_motion: Option<glm::Vec3>,
+    /// Pixel from the top left corner of the image whose geometry is focused on.
+    pub focus_at: Option<(u32, u32)>,
+    /// Keyframes of the camera, see [`CameraAnimation::load`]. When set, the animation is
+    /// rendered frame by frame next to `output`, numbered from `_0000`.
+    pub camera_path: Option<PathBuf>,
+    /// Frames per second of the animation.
+    pub fps: f32,
+    /// The mean of the samples is written there as a PFM image, its settings next to it
+    /// in a text file.
+    pub output: PathBuf,
+    /// Where the accumulation is saved every `checkpoint_interval` and at the end.
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    /// Checkpoint to continue from, rendered with the same size and scene.
+    pub resume: Option<PathBuf>,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            scene: String::from("cornell"),
+            size: (800, 800),
+            samples: RenderParam::default().samples_max_per_pixel,
+            samples_per_frame: 16,
+            sampler: SamplerKind::Independent,
+            filter: PixelFilter::Box,
+            filter_radius: None,
+            seed: 0,
+            clamp_direct: 0.0,
+            clamp_indirect: 0.0,
+            outlier_sigma: 0.0,
+            max_time: None,
+            noise_threshold: None,
+            adaptive: false,
+            mode: TracerMode::Megakernel,
+            bookmark: None,
+            projection: None,
+            aperture: None,
+            aperture_blades: None,
+            aperture_rotation: None,
+            anamorphic: None,
+            aperture_mask: None,
+            shutter: None,
+            camera_motion: None,
+            focus_at: None,
+            camera_path: None,
+            fps: 24.0,
+            output: PathBuf::from("render.pfm"),
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(60),
+            resume: None,
+        }
+    }
+}
+
+impl HeadlessOptions {
+    pub const USAGE: &'static str = "\
+--headless [--scene cornell|oneweek|motion-blur] [--size WIDTHxHEIGHT] [--samples N]
+           [--samples-per-frame N] [--seed N] [--sampler independent|sobol|owen-sobol|blue-noise]
+           [--filter box|tent|gaussian|mitchell|blackman-harris] [--filter-radius PIXELS]
+           [--clamp-direct LUMINANCE] [--clamp-indirect LUMINANCE] [--outlier-sigma SIGMA]
+           [--max-time SECONDS] [--noise-threshold ERROR] [--adaptive] [--wavefront]
+           [--output FILE.pfm] [--bookmark NAME]
+           [--projection perspective|orthographic|fisheye|equirectangular|stereo-equirectangular]
+           [--aperture DIAMETER] [--aperture-blades N] [--aperture-rotation DEGREES]
+           [--anamorphic SQUEEZE] [--aperture-mask FILE.pfm]
+           [--shutter OPEN,CLOSE] [--camera-motion X,Y,Z] [--focus-at X,Y]
+           [--camera-path FILE] [--fps N]
+           [--checkpoint FILE] [--checkpoint-interval SECONDS] [--resume FILE]";
+
+    /// Parses the command line arguments, `Ok(None)` when `--headless` is not among them.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut args = args.into_iter();
+        let mut options = Self::default();
+        let mut headless = false;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value after {}", arg));
+            let number = |value: String| {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| format!("invalid number {}", value))
+            };
+            let count = |value: String| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("invalid number {}", value))
+            };
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--scene" => {
+                    let value = value()?;
+                    if !Scene::NAMES.contains(&value.as_str()) {
+                        return Err(format!("unknown scene {}", value));
+                    }
+                    options.scene = value;
+                }
+                "--size" => {
+                    let value = value()?;
+                    let (width, height) = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                        .ok_or(format!("invalid size {}", value))?;
+                    options.size = (width, height);
+                }
+                "--samples" => options.samples = count(value()?)?,
+                "--samples-per-frame" => options.samples_per_frame = count(value()?)?,
+                "--sampler" => {
+                    let value = value()?;
+                    options.sampler = SamplerKind::from_name(&value)
+                        .ok_or(format!("unknown sampler {}", value))?;
+                }
+                "--filter" => {
+                    let value = value()?;
+                    options.filter = PixelFilter::from_name(&value)
+                        .ok_or(format!("unknown filter {}", value))?;
+                }
+                "--filter-radius" => options.filter_radius = Some(number(value()?)?),
+                "--seed" => {
+                    let value = value()?;
+                    options.seed = value
+                        .parse()
+                        .map_err(|_| format!("invalid seed {}", value))?;
+                }
+                "--clamp-direct" => options.clamp_direct = number(value()?)?,
+                "--clamp-indirect" => options.clamp_indirect = number(value()?)?,
+                "--outlier-sigma" => options.outlier_sigma = number(value()?)?,
+                "--max-time" => {
+                    options.max_time = Some(Duration::from_secs_f32(number(value()?)?));
+                }
+                "--noise-threshold" => options.noise_threshold = Some(number(value()?)?),
+                "--adaptive" => options.adaptive = true,
+                "--wavefront" => options.mode = TracerMode::Wavefront,
+                "--bookmark" => options.bookmark = Some(value()?),
+                "--projection" => {
+                    let value = value()?;
+                    options.projection = Some(
+                        Projection::from_name(&value)
+                            .ok_or(format!("unknown projection {}", value))?,
+                    );
+                }
+                "--aperture" => options.aperture = Some(number(value()?)?),
+                "--aperture-blades" => options.aperture_blades = Some(count(value()?)?),
+                "--aperture-rotation" => options.aperture_rotation = Some(number(value()?)?),
+                "--anamorphic" => options.anamorphic = Some(number(value()?)?),
+                "--aperture-mask" => options.aperture_mask = Some(value()?.into()),
+                "--shutter" => {
+                    let value = value()?;
+                    let (open, close) = value
+                        .split_once(',')
+                        .and_then(|(open, close)| Some((open.parse().ok()?, close.parse().ok()?)))
+                        .ok_or(format!("invalid shutter {}", value))?;
+                    options.shutter = Some(Shutter { open, close });
+                }
+                "--camera-motion" => {
+                    let value = value()?;
+                    let coordinates = value
+                        .split(',')
+                        .map(str::parse)
+                        .collect::<Result<Vec<f32>, _>>()
+                        .ok()
+                        .filter(|coordinates| coordinates.len() == 3)
+                        .ok_or(format!("invalid camera motion {}", value))?;
+                    options.camera_motion = Some(glm::make_vec3(&coordinates));
+                }
+                "--focus-at" => {
+                    let value = value()?;
+                    options.focus_at = Some(
+                        value
+                            .split_once(',')
+                            .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+                            .ok_or(format!("invalid pixel {}", value))?,
+                    );
+                }
+                "--camera-path" => options.camera_path = Some(value()?.into()),
+                "--fps" => {
+                    options.fps = number(value()?)?;
+                    if options.fps <= 0.0 {
+                        return Err(format!("invalid frame rate {}", options.fps));
+                    }
+                }
+                "--output" => options.output = value()?.into(),
+                "--checkpoint" => options.checkpoint = Some(value()?.into()),
+                "--checkpoint-interval" => {
+                    options.checkpoint_interval = Duration::from_secs_f32(number(value()?)?);
+                }
+                "--resume" => options.resume = Some(value()?.into()),
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        if options.camera_path.is_some()
+            && (options.checkpoint.is_some() || options.resume.is_some())
+        {
+            return Err(String::from(
+                "--camera-path renders a sequence, which cannot be checkpointed",
+            ));
+        }
+        Ok(headless.then_some(options))
+    }
+}
+
+/// Renders `options.scene` without a window until a stop criterion is met.
+pub async fn run_headless(options: HeadlessOptions) -> io::Result<()> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all),
+        ..Default::default()
+    });
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        })
+        .await
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no suitable GPU adapter"))?;
+    log::info!("Adapter: {:?}", adapter.get_info());
+    let adapter_limits = adapter.limits();
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits {
+                    max_storage_buffer_binding_size: adapter_limits
+                        .max_storage_buffer_binding_size
+                        .min(512 << 20),
+                    max_storage_buffers_per_shader_stage: adapter_limits
+                        .max_storage_buffers_per_shader_stage
+                        .min(16),
+                    ..Default::default()
+                },
+                label: Some("Headless device"),
+                memory_hints: Default::default(),
+            },
+            None,
+        )
+        .await
+        .map_err(io::Error::other)?;
+
+    let mut scene = Scene::from_name(
+        &options.scene,
+        RenderParam {
+            samples_max_per_pixel: options.samples,
+            sampler_kind: options.sampler as u32,
+            adaptive: options.adaptive as u32,
+            filter_kind: options.filter as u32,
+            filter_radius: options
+                .filter_radius
+                .unwrap_or(options.filter.default_radius()),
+            seed: options.seed,
+            clamp_direct: options.clamp_direct,
+            clamp_indirect: options.clamp_indirect,
+            outlier_sigma: options.outlier_sigma,
+            noise_threshold: options
+                .noise_threshold
+                .unwrap_or(RenderParam::default().noise_threshold),
+            ..Default::default()
+        },
+        FrameData {
+            width: options.size.0,
+            height: options.size.1,
+            index: 0,
+        },
+    )
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no scene {}", options.scene),
+        )
+    })?;
+    if let Some(name) = &options.bookmark {
+        let bookmark = scene
+            .bookmarks
+            .iter()
+            .find(|bookmark| &bookmark.name == name)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("no bookmark {}", name))
+            })?;
+        scene.camera = bookmark.camera;
+    }
+    if let Some(projection) = options.projection {
+        scene.camera.projection = projection;
+    }
+    if let Some(aperture) = options.aperture {
+        scene.camera.aperture = aperture;
+    }
+    if let Some(blades) = options.aperture_blades {
+        scene.camera.aperture_shape.blades = blades;
+    }
+    if let Some(rotation) = options.aperture_rotation {
+        scene.camera.aperture_shape.rotation = rotation;
+    }
+    if let Some(squeeze) = options.anamorphic {
+        scene.camera.aperture_shape.squeeze = squeeze;
+    }
+    if let Some(shutter) = options.shutter {
+        scene.camera.shutter = shutter;
+    }
+    if let Some(motion) = options.camera_motion {
+        scene.camera.motion = Transform::translation(motion);
+    }
+    if let Some(path) = &options.aperture_mask {
+        scene.aperture_mask = Some(read_pfm(path)?);
+    }
+    let mut path_tracer = PathTracer::new(&device, &scene, options.size);
+    path_tracer.set_mode(options.mode);
+    let criteria = StopCriteria {
+        max_time: options.max_time,
+        noise: options.noise_threshold.is_some(),
+    };
+
+    if let Some(path) = &options.camera_path {
+        let animation = CameraAnimation::load(path)?;
+        if animation.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: no keyframes", path.display()),
+            ));
+        }
+        let camera = scene.camera;
+        let frame_count = animation.frame_count(options.fps);
+        for frame in 0..frame_count {
+            let time = animation.start() + frame as f32 / options.fps;
+            scene.camera = animation.camera_at(time, &camera);
+            focus_at(&mut scene, &options);
+            scene.render_param.total_samples = 0;
+            let mut progress = Progress::new(criteria);
+            render(
+                &device,
+                &queue,
+                &mut path_tracer,
+                &mut scene,
+                &mut progress,
+                &options,
+            )?;
+
+            let checkpoint = path_tracer.checkpoint(&device, &queue, &scene, progress.elapsed());
+            let output = frame_path(&options.output, frame);
+            write_pfm(&output, checkpoint.size, &checkpoint.mean_image())?;
+            write_settings(&output.with_extension("txt"), &checkpoint)?;
+            log::info!(
+                "Frame {}/{} at {:.2} s saved to {}",
+                frame + 1,
+                frame_count,
+                time,
+                output.display()
+            );
+        }
+        return Ok(());
+    }
+
+    focus_at(&mut scene, &options);
+    let mut progress = Progress::new(criteria);
+    if let Some(path) = &options.resume {
+        let checkpoint = Checkpoint::load(path, options.size)?;
+        path_tracer.restore(&queue, &mut scene, &checkpoint)?;
+        // The limits given now win over the ones of the checkpoint to render further
+        scene.render_param.samples_max_per_pixel = options.samples;
+        progress.restore(checkpoint.elapsed);
+        log::info!(
+            "Resuming {} at {} samples per pixel",
+            path.display(),
+            scene.render_param.total_samples
+        );
+    }
+    render(
+        &device,
+        &queue,
+        &mut path_tracer,
+        &mut scene,
+        &mut progress,
+        &options,
+    )?;
+
+    let checkpoint = path_tracer.checkpoint(&device, &queue, &scene, progress.elapsed());
+    if let Some(path) = &options.checkpoint {
+        checkpoint.save(path)?;
+    }
+    write_pfm(&options.output, checkpoint.size, &checkpoint.mean_image())?;
+    write_settings(&options.output.with_extension("txt"), &checkpoint)?;
+    log::info!("Image saved to {}", options.output.display());
+    Ok(())
+}
+
+/// Focuses the camera of `scene` on the geometry under `options.focus_at`, if any.
+fn focus_at(scene: &mut Scene, options: &HeadlessOptions) {
+    if let Some((x, y)) = options.focus_at {
+        // The image rows go from bottom to top
+        let uv = glm::vec2(
+            (x as f32 + 0.5) / options.size.0 as f32,
+            1.0 - (y as f32 + 0.5) / options.size.1 as f32,
+        );
+        match scene.focus_at(options.size, uv) {
+            Some(_) => log::info!("Focus distance set to {}", scene.camera.focus_distance),
+            None => log::warn!("Nothing to focus on at pixel {},{}", x, y),
+        }
+    }
+}
+
+/// Accumulates samples until one of the stop criteria of `progress` is met, saving the
+/// checkpoints asked for along the way.
+fn render(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path_tracer: &mut PathTracer,
+    scene: &mut Scene,
+    progress: &mut Progress,
+    options: &HeadlessOptions,
+) -> io::Result<()> {
+    let mut budget = FrameBudget::fixed(options.samples_per_frame);
+    budget.set_limit(path_tracer.samples_per_frame_limit(&scene.render_param));
+    let mut last_frame = Instant::now();
+    let mut last_checkpoint = Instant::now();
+    loop {
+        let now = Instant::now();
+        let converged = progress.update(&scene.render_param, now - last_frame);
+        last_frame = now;
+        if let Some(converged) = converged {
+            log::info!(
+                "Render converged ({:?}) after {} samples per pixel in {:.1?}",
+                converged.reason,
+                converged.samples,
+                converged.elapsed
+            );
+            return Ok(());
+        }
+
+        scene.frame_data.index += 1;
+        scene.render_param.update(&budget);
+        path_tracer.update_uniforms(queue, scene);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless encoder"),
+        });
+        path_tracer.dispatch(&mut encoder, None);
+        queue.submit(std::iter::once(encoder.finish()));
+        path_tracer.after_submit();
+        device.poll(wgpu::Maintain::Wait);
+        if let Some(noisy_pixels) = path_tracer.read_noisy_pixels(device) {
+            progress.record_noisy_pixels(noisy_pixels, progress.restarts());
+        }
+
+        if let Some(path) = &options.checkpoint {
+            if last_checkpoint.elapsed() >= options.checkpoint_interval {
+                path_tracer
+                    .checkpoint(device, queue, scene, progress.elapsed())
+                    .save(path)?;
+                last_checkpoint = Instant::now();
+                log::info!(
+                    "Checkpoint at {} samples per pixel saved to {}",
+                    scene.render_param.total_samples,
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+/// `output` with the frame number appended to its name, `render_0042.pfm` for frame 42.
+fn frame_path(output: &std::path::Path, frame: u32) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}_{:04}", stem, frame);
+    if let Some(extension) = output.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    output.with_file_name(name)
+}
+
+/// Records next to the image the settings it was rendered with, PFM has no room for them.
+fn write_settings(path: &std::path::Path, checkpoint: &Checkpoint) -> io::Result<()> {
+    let param = &checkpoint.render_param;
+    let disabled_if_zero = |value: f32| {
+        if value > 0.0 {
+            value.to_string()
+        } else {
+            String::from("off")
+        }
+    };
+
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(writer, "size: {}x{}", checkpoint.size.0, checkpoint.size.1)?;
+    writeln!(writer, "samples per pixel: {}", param.total_samples)?;
+    writeln!(writer, "render time: {:.1?}", checkpoint.elapsed)?;
+    writeln!(writer, "tracer: {:?}", checkpoint.mode)?;
+    writeln!(writer, "sampler: {:?}", param.sampler())?;
+    writeln!(writer, "seed: {}", param.seed)?;
+    writeln!(
+        writer,
+        "pixel filter: {:?}, radius {}",
+        param.filter(),
+        param.filter_radius
+    )?;
+    writeln!(writer, "max depth: {}", param.max_depth)?;
+    writeln!(writer, "russian roulette depth: {}", param.rr_min_depth)?;
+    writeln!(writer, "adaptive sampling: {}", param.adaptive != 0)?;
+    writeln!(writer, "noise threshold: {}", param.noise_threshold)?;
+    writeln!(
+        writer,
+        "clamp direct: {}",
+        disabled_if_zero(param.clamp_direct)
+    )?;
+    writeln!(
+        writer,
+        "clamp indirect: {}",
+        disabled_if_zero(param.clamp_indirect)
+    )?;
+    writeln!(
+        writer,
+        "outlier rejection: {}",
+        disabled_if_zero(param.outlier_sigma)
+    )?;
+    writer.flush()
+}
+
+/// Writes a linear RGB image as PFM, rows go from bottom to top like the image buffer.
+fn write_pfm(path: &std::path::Path, size: (u32, u32), pixels: &[[f32; 3]]) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    // A negative scale means little endian
+    write!(writer, "PF\n{} {}\n-1.0\n", size.0, size.1)?;
+    for channel in pixels.iter().flatten() {
+        writer.write_all(&channel.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Reads a PFM image, color or grayscale, as a texture stored from the top row down.
+fn read_pfm(path: &std::path::Path) -> io::Result<Texture> {
+    let reader = io::BufReader::new(std::fs::File::open(path)?);
+    decode_pfm(reader)
+        .map_err(|error| io::Error::new(error.kind(), format!("{}: {}", path.display(), error)))
+}
+
+/// Largest width or height of the PFM images read, far above any aperture mask.
+const MAX_PFM_SIDE: usize = 16384;
+
+fn decode_pfm(mut reader: impl BufRead) -> io::Result<Texture> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut header = Vec::new();
+    // Magic, size and scale are separated by whitespace, the data starts after one more
+    while header.len() < 4 {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("truncated header"));
+        }
+        header.extend(line.split_whitespace().map(String::from));
+    }
+    let channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("not a PFM image")),
+    };
+    let (width, height, scale) = match header[1..] {
+        [ref width, ref height, ref scale] => (
+            width
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid width"))?,
+            height
+                .parse::<usize>()
+                .map_err(|_| invalid("invalid height"))?,
+            scale.parse::<f32>().map_err(|_| invalid("invalid scale"))?,
+        ),
+        _ => return Err(invalid("invalid header")),
+    };
+    if width == 0 || height == 0 || width > MAX_PFM_SIDE || height > MAX_PFM_SIDE {
+        return Err(invalid("invalid size"));
+    }
+
+    let len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels * 4))
+        .ok_or_else(|| invalid("invalid size"))?;
+    // Read up to the expected length rather than allocating it up front, a truncated
+    // file then fails without reserving the memory its header asks for
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(invalid("truncated data"));
+    }
+    let values: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|chunk| {
+            let chunk = chunk.try_into().unwrap();
+            if scale < 0.0 {
+                f32::from_le_bytes(chunk)
+            } else {
+                f32::from_be_bytes(chunk)
+            }
+        })
+        .collect();
+    let pixels: Vec<[f32; 3]> = values
+        .chunks_exact(channels)
+        .map(|pixel| match pixel {
+            [r, g, b] => [*r, *g, *b],
+            _ => [pixel[0]; 3],
+        })
+        .collect();
+    // PFM rows go from bottom to top
+    let rows = pixels.chunks_exact(width).rev().flatten().copied();
+    Ok(Texture::new((width as u32, height as u32), rows.collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pfm(header: &str, values: &[f32]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend(values.iter().flat_map(|value| value.to_le_bytes()));
+        bytes
+    }
+
+    fn args(args: &[&str]) -> Result<Option<HeadlessOptions>, String> {
+        HeadlessOptions::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_counts() {
+        let options = args(&[
+            "--headless",
+            "--samples",
+            "4096",
+            "--samples-per-frame",
+            "8",
+            "--aperture-blades",
+            "6",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(options.samples, 4096);
+        assert_eq!(options.samples_per_frame, 8);
+        assert_eq!(options.aperture_blades, Some(6));
+    }
+
+    #[test]
+    fn rejects_invalid_counts() {
+        for flag in ["--samples", "--samples-per-frame", "--aperture-blades"] {
+            for value in ["-5", "2.7", "1e20", "4294967296", "many"] {
+                assert_eq!(
+                    args(&["--headless", flag, value]).err(),
+                    Some(format!("invalid number {}", value)),
+                    "{} {}",
+                    flag,
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_the_scene_camera_motion_without_the_flag() {
+        assert_eq!(args(&["--headless"]).unwrap().unwrap().camera_motion, None);
+        let options = args(&["--headless", "--camera-motion", "1,0,-2"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.camera_motion, Some(glm::vec3(1.0, 0.0, -2.0)));
+    }
+
+    #[test]
+    fn keeps_the_scene_aperture_shape_without_the_flags() {
+        let options = args(&["--headless"]).unwrap().unwrap();
+        assert_eq!(options.aperture_blades, None);
+        assert_eq!(options.aperture_rotation, None);
+        assert_eq!(options.anamorphic, None);
+        let options = args(&["--headless", "--anamorphic", "2"]).unwrap().unwrap();
+        assert_eq!(options.anamorphic, Some(2.0));
+        assert_eq!(options.aperture_blades, None);
+    }
+
+    #[test]
+    fn decodes_rows_from_the_top() {
+        let texture = decode_pfm(&pfm("Pf\n2 2\n-1.0\n", &[1.0, 2.0, 3.0, 4.0])[..]).unwrap();
+        assert_eq!(
+            texture.as_slice(),
+            &[[3.0; 3], [4.0; 3], [1.0; 3], [2.0; 3]]
+        );
+
+        let texture = decode_pfm(&pfm("PF 1 1 -1\n", &[0.25, 0.5, 0.75])[..]).unwrap();
+        assert_eq!(texture.as_slice(), &[[0.25, 0.5, 0.75]]);
+    }
+
+    #[test]
+    fn rejects_malformed_headers() {
+        for header in [
+            "PF\n0 4\n-1.0\n",
+            "PF\n4 0\n-1.0\n",
+            "PF\n100000 100000\n-1.0\n",
+            "PF\n4294967295 4294967295\n-1.0\n",
+            "PF\n18446744073709551616 1\n-1.0\n",
+            "PF\n-2 2\n-1.0\n",
+            "PF\n2 2\nscale\n",
+            "P6\n2 2\n-1.0\n",
+            "PF\n2 2\n",
+        ] {
+            let error = decode_pfm(&pfm(header, &[0.0; 12])[..]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let error = decode_pfm(&pfm("PF\n2 2\n-1.0\n", &[0.0; 11])[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -6,11 +6,13 @@ use wasm_bindgen::prelude::*;
 use winit::{
     application::ApplicationHandler,
     event::*,
-    event_loop::{ActiveEventLoop, EventLoop},
+    event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
     keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowAttributes, WindowId},
 };
 
+mod path_tracer;
+
 mod render_context;
 use render_context::RenderContext;
 
@@ -20,10 +22,19 @@ mod scene;
 extern crate nalgebra_glm as glm;
 
 mod object;
-struct MyUserEvent;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod headless;
+#[cfg(not(target_arch = "wasm32"))]
+pub use headless::{run_headless, HeadlessOptions};
+enum MyUserEvent {
+    /// The progressive render met one of its stop criteria.
+    Converged(scene::Converged),
+}
 
 struct State<'a> {
     window: &'a Window,
+    event_loop_proxy: EventLoopProxy<MyUserEvent>,
     render_context: RenderContext<'a>,
     last_time: instant::Instant,
     mouse_pressed: bool,
@@ -32,8 +43,15 @@ struct State<'a> {
 }
 
 impl ApplicationHandler<MyUserEvent> for State<'_> {
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, _user_eventt: MyUserEvent) {
-        // Handle user event.
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, user_event: MyUserEvent) {
+        match user_event {
+            MyUserEvent::Converged(converged) => log::info!(
+                "Render converged ({:?}) after {} samples per pixel in {:.1?}",
+                converged.reason,
+                converged.samples,
+                converged.elapsed
+            ),
+        }
     }
 
     fn resumed(&mut self, _event_loop: &ActiveEventLoop) {
@@ -49,8 +67,12 @@ impl ApplicationHandler<MyUserEvent> for State<'_> {
         self.render_context
             .window_event(&event, &mut self.mouse_pressed);
         match event {
-            WindowEvent::CloseRequested
-            | WindowEvent::KeyboardInput {
+            WindowEvent::CloseRequested => {
+                self.render_context.on_exit();
+                event_loop.exit();
+            }
+            // Browsers leave the pointer lock with Escape, it only closes the native app
+            WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
                         state: ElementState::Pressed,
@@ -58,7 +80,10 @@ impl ApplicationHandler<MyUserEvent> for State<'_> {
                         ..
                     },
                 ..
-            } => event_loop.exit(),
+            } if !cfg!(target_arch = "wasm32") => {
+                self.render_context.on_exit();
+                event_loop.exit();
+            }
             WindowEvent::RedrawRequested => {
                 self.window.request_redraw();
                 if !self.surface_configured {
@@ -73,9 +98,16 @@ impl ApplicationHandler<MyUserEvent> for State<'_> {
 
                 self.render_context.update(dt);
                 match self.render_context.render() {
-                    Ok(_) => {}
+                    Ok(_) => {
+                        if let Some(converged) = self.render_context.take_converged() {
+                            let _ = self
+                                .event_loop_proxy
+                                .send_event(MyUserEvent::Converged(converged));
+                        }
+                    }
                     Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
-                        self.render_context.resize(self.render_context.size)
+                        self.surface_configured =
+                            self.render_context.resize(self.window.inner_size());
                     }
                     Err(wgpu::SurfaceError::OutOfMemory) => {
                         log::error!("Out of memory");
@@ -88,8 +120,12 @@ impl ApplicationHandler<MyUserEvent> for State<'_> {
                 }
             }
             WindowEvent::Resized(physical_size) => {
-                self.surface_configured = true;
-                self.render_context.resize(physical_size);
+                // A minimised window reports a zero size, stop rendering until restored
+                self.surface_configured = self.render_context.resize(physical_size);
+            }
+            WindowEvent::ScaleFactorChanged { .. } => {
+                // The new physical size is not part of the event, read it back from the window
+                self.surface_configured = self.render_context.resize(self.window.inner_size());
             }
             _ => {}
         }
@@ -110,9 +146,10 @@ impl ApplicationHandler<MyUserEvent> for State<'_> {
     }
 }
 
+/// Sets up the logging and creates the window from `attributes`, on the page canvas for
+/// the web.
 fn init(
-    width: u32,
-    height: u32,
+    attributes: WindowAttributes,
 ) -> (
     winit::window::Window,
     winit::event_loop::EventLoop<MyUserEvent>,
@@ -129,13 +166,14 @@ fn init(
     log::info!("Starting up");
 
     let event_loop = EventLoop::<MyUserEvent>::with_user_event().build().unwrap();
-    #[allow(unused_mut)]
-    let mut attributes =
-        WindowAttributes::default().with_inner_size(winit::dpi::PhysicalSize::new(width, height));
 
+    // The page lays out the canvas, see index.html, winit follows its size
     #[cfg(target_arch = "wasm32")]
-    {
+    let attributes = {
         use winit::platform::web::WindowAttributesExtWebSys;
+        // The mouse motion turning the camera is reported even before the canvas has
+        // the focus, on the first drag
+        event_loop.listen_device_events(winit::event_loop::DeviceEvents::Always);
         let canvas = wgpu::web_sys::window()
             .unwrap()
             .document()
@@ -144,13 +182,13 @@ fn init(
             .unwrap()
             .dyn_into::<web_sys::HtmlCanvasElement>()
             .unwrap();
-        attributes = attributes.with_canvas(Some(canvas));
-    }
+        attributes.with_canvas(Some(canvas))
+    };
 
     #[allow(deprecated)]
     let window = event_loop.create_window(attributes).unwrap();
 
-    return (window, event_loop);
+    (window, event_loop)
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
@@ -159,23 +197,21 @@ pub async fn run() {
     let scale = 2.2;
     let width = 500 * scale as u32;
     let height = 550 * scale as u32;
-    let (window, event_loop) = init(width, height);
+    let attributes = WindowAttributes::default();
+    #[cfg(not(target_arch = "wasm32"))]
+    let attributes = attributes.with_inner_size(winit::dpi::PhysicalSize::new(width, height));
+    let (window, event_loop) = init(attributes);
 
     let mut state = State {
         window: &window,
+        event_loop_proxy: event_loop.create_proxy(),
         mouse_pressed: false,
         surface_configured: false,
         last_time: instant::Instant::now(),
         render_context: RenderContext::new(
             &window,
             &Scene::cornell_scene(
-                scene::RenderParam {
-                    samples_per_pixel: 1,
-                    max_depth: 30,
-                    samples_max_per_pixel: 1000,
-                    total_samples: 0,
-                    clear_samples: 0,
-                },
+                scene::RenderParam::default(),
                 scene::FrameData {
                     width,
                     height,
@@ -1,5 +1,27 @@
 use pathracer_wgpu::run;
 
+#[cfg(target_arch = "wasm32")]
 fn main() {
     pollster::block_on(run());
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use pathracer_wgpu::{run_headless, HeadlessOptions};
+
+    match HeadlessOptions::from_args(std::env::args().skip(1)) {
+        Ok(None) => pollster::block_on(run()),
+        Ok(Some(options)) => {
+            env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+                .init();
+            if let Err(error) = pollster::block_on(run_headless(options)) {
+                log::error!("Headless render failed: {}", error);
+                std::process::exit(1);
+            }
+        }
+        Err(error) => {
+            eprintln!("{}\nUsage: {}", error, HeadlessOptions::USAGE);
+            std::process::exit(2);
+        }
+    }
+}
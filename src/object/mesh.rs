@@ -66,6 +66,7 @@ impl Mesh {
         ]
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn empty() -> Self {
         Self {
             vertices: [glm::vec4(0.0, 0.0, 0.0, 0.0); 3],
@@ -187,7 +188,7 @@ impl Mesh {
     }
 }
 
-pub fn rotate(meshes: &mut Vec<Mesh>, angle: f32, axis: glm::Vec3) {
+pub fn rotate(meshes: &mut [Mesh], angle: f32, axis: glm::Vec3) {
     // degree to radian
     let angle = angle.to_radians();
     let rotation = glm::quat_angle_axis(angle, &axis);
@@ -209,7 +210,7 @@ pub fn rotate(meshes: &mut Vec<Mesh>, angle: f32, axis: glm::Vec3) {
     }
 }
 
-pub fn translate(meshes: &mut Vec<Mesh>, translation: glm::Vec3) {
+pub fn translate(meshes: &mut [Mesh], translation: glm::Vec3) {
     for mesh in meshes.iter_mut() {
         for vertex in mesh.vertices.iter_mut() {
             vertex.x += translation.x;
@@ -219,7 +220,7 @@ pub fn translate(meshes: &mut Vec<Mesh>, translation: glm::Vec3) {
     }
 }
 
-pub fn scale(meshes: &mut Vec<Mesh>, scale: glm::Vec3) {
+pub fn scale(meshes: &mut [Mesh], scale: glm::Vec3) {
     for mesh in meshes.iter_mut() {
         for vertex in mesh.vertices.iter_mut() {
             vertex.x *= scale.x;
@@ -4,12 +4,19 @@ pub use sphere::Sphere;
 mod mesh;
 pub use mesh::{rotate, scale, translate, Mesh};
 
+mod motion;
+pub use motion::Transform;
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, PartialEq)]
 pub struct Object {
     pub id: u32,
     pub obj_type: u32,
     pub count: u32,
+    /// Non zero when `motion` moves the object, the shader skips the transforms otherwise.
+    pub moving: u32,
+    /// Transforms of the geometry at time 0 and 1, interpolated at the time of each ray.
+    pub motion: [Transform; 2],
 }
 
 impl Object {
@@ -18,8 +25,26 @@ impl Object {
             id,
             obj_type: obj_type as u32,
             count: count.unwrap_or(1) as u32,
+            moving: 0,
+            motion: [Transform::identity(); 2],
+        }
+    }
+
+    /// The object moved by `start` at time 0 to `end` at time 1.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_motion(self, start: Transform, end: Transform) -> Self {
+        let moving = start != Transform::identity() || end != Transform::identity();
+        Self {
+            moving: moving as u32,
+            motion: [start, end],
+            ..self
         }
     }
+
+    /// Transform of the geometry at `time`.
+    pub fn transform(&self, time: f32) -> Transform {
+        self.motion[0].interpolate(&self.motion[1], time)
+    }
 }
 
 #[allow(dead_code)]
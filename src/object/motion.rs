@@ -0,0 +1,68 @@
+/// Rigid transform, a rotation about the origin followed by a translation. Mirrors
+/// `Transform` in motion.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, PartialEq)]
+pub struct Transform {
+    pub translation: glm::Vec4,
+    /// Unit quaternion, `w` last.
+    pub rotation: glm::Vec4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self::translation(glm::vec3(0.0, 0.0, 0.0))
+    }
+
+    pub fn translation(translation: glm::Vec3) -> Self {
+        Self {
+            translation: glm::vec3_to_vec4(&translation),
+            rotation: glm::quat_identity().coords,
+        }
+    }
+
+    /// Rotation of `angle` degrees around `axis` going through `center`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn rotation_about(center: glm::Vec3, axis: glm::Vec3, angle: f32) -> Self {
+        let rotation = glm::quat_angle_axis(angle.to_radians(), &glm::normalize(&axis));
+        let translation = center - glm::quat_rotate_vec3(&rotation, &center);
+        Self {
+            translation: glm::vec3_to_vec4(&translation),
+            rotation: rotation.coords,
+        }
+    }
+
+    fn quat(&self) -> glm::Quat {
+        glm::Quat::from_vector(self.rotation)
+    }
+
+    /// Transform at `time` between `self` at 0 and `end` at 1, as `interpolate_transform`
+    /// in the shader.
+    pub fn interpolate(&self, end: &Self, time: f32) -> Self {
+        Self {
+            translation: glm::lerp(&self.translation, &end.translation, time),
+            rotation: glm::quat_slerp(&self.quat(), &end.quat(), time).coords,
+        }
+    }
+
+    pub fn transform_point(&self, point: glm::Vec3) -> glm::Vec3 {
+        self.transform_vector(point) + self.translation.xyz()
+    }
+
+    pub fn transform_vector(&self, vector: glm::Vec3) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&self.quat(), &vector)
+    }
+
+    pub fn inverse_transform_point(&self, point: glm::Vec3) -> glm::Vec3 {
+        self.inverse_transform_vector(point - self.translation.xyz())
+    }
+
+    pub fn inverse_transform_vector(&self, vector: glm::Vec3) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&glm::quat_conjugate(&self.quat()), &vector)
+    }
+}
@@ -1,30 +1,44 @@
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, PartialEq)]
 pub struct Sphere {
-    pub center: glm::Vec4,  // 0 byte offset
+    /// Center at time 0.
+    pub center0: glm::Vec4, // 0 byte offset
     pub radius: f32,        // 16 byte offset
     pub material_idx: u32,  // 20 byte offset
     pub _padding: [u32; 2], // 24 byte offset, 8 bytes size
+    /// Center at time 1, the sphere moves in a straight line in between.
+    pub center1: glm::Vec4, // 32 byte offset
 }
 
 impl Sphere {
     #[allow(dead_code)]
     pub fn empty() -> Self {
         Self {
-            center: glm::Vec4::new(0.0, 0.0, 0.0, 0.0),
+            center0: glm::Vec4::new(0.0, 0.0, 0.0, 0.0),
             radius: 0.0,
             material_idx: 0,
             _padding: [0; 2],
+            center1: glm::Vec4::new(0.0, 0.0, 0.0, 0.0),
         }
     }
 
     #[allow(dead_code)]
     pub fn new(center: glm::Vec3, radius: f32) -> Self {
+        Self::moving(center, center, radius)
+    }
+
+    pub fn moving(center0: glm::Vec3, center1: glm::Vec3, radius: f32) -> Self {
         Self {
-            center: glm::vec3_to_vec4(&center),
+            center0: glm::vec3_to_vec4(&center0),
             radius,
             material_idx: 0,
             _padding: [0; 2],
+            center1: glm::vec3_to_vec4(&center1),
         }
     }
+
+    /// Center at `time`, as `sphere_center` in the shader.
+    pub fn center(&self, time: f32) -> glm::Vec3 {
+        glm::lerp(&self.center0.xyz(), &self.center1.xyz(), time)
+    }
 }
@@ -0,0 +1,290 @@
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use bytemuck::Zeroable;
+
+use super::{GpuPixelStats, TracerMode};
+use crate::scene::{GpuCamera, RenderParam, Scene};
+
+/// Everything needed to continue an accumulation later, possibly on another machine.
+///
+/// The samples depend on the render seed, the sample count of each pixel and the tracer,
+/// so restoring them makes the continuation identical to an uninterrupted render.
+pub struct Checkpoint {
+    /// See [`scene_hash`], a checkpoint only applies to the scene it was taken from.
+    pub scene_hash: u64,
+    pub size: (u32, u32),
+    /// `FrameData::index` of the last traced frame.
+    pub frame_index: u32,
+    pub mode: TracerMode,
+    pub render_param: RenderParam,
+    /// Rendering time accumulated before the checkpoint.
+    pub elapsed: Duration,
+    pub image: Vec<[f32; 3]>,
+    pub pixel_stats: Vec<GpuPixelStats>,
+}
+
+impl Checkpoint {
+    const MAGIC: [u8; 8] = *b"PTRACKPT";
+    /// Bumped whenever the layout of the file or of the GPU structs changes.
+    const VERSION: u32 = 4;
+    /// Bytes before the buffers.
+    const HEADER_LEN: u64 = 44 + std::mem::size_of::<RenderParam>() as u64;
+
+    /// Bytes of the checkpoint of a `size` render, `None` when it does not fit in a file.
+    fn file_len(size: (u32, u32)) -> Option<u64> {
+        let pixel_len = std::mem::size_of::<[f32; 3]>() + std::mem::size_of::<GpuPixelStats>();
+        (size.0 as u64)
+            .checked_mul(size.1 as u64)?
+            .checked_mul(pixel_len as u64)?
+            .checked_add(Self::HEADER_LEN)
+    }
+
+    /// Writes the checkpoint, the buffers are stored with the byte order of the host,
+    /// little endian on every supported platform.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&Self::MAGIC)?;
+        writer.write_all(&Self::VERSION.to_le_bytes())?;
+        writer.write_all(&self.scene_hash.to_le_bytes())?;
+        writer.write_all(&self.size.0.to_le_bytes())?;
+        writer.write_all(&self.size.1.to_le_bytes())?;
+        writer.write_all(&self.frame_index.to_le_bytes())?;
+        writer.write_all(&(self.mode as u32).to_le_bytes())?;
+        writer.write_all(&(self.elapsed.as_millis() as u64).to_le_bytes())?;
+        writer.write_all(bytemuck::bytes_of(&self.render_param))?;
+        writer.write_all(bytemuck::cast_slice(&self.image))?;
+        writer.write_all(bytemuck::cast_slice(&self.pixel_stats))?;
+        Ok(())
+    }
+
+    /// Reads a checkpoint of a `size` render. The size is checked before the buffers are
+    /// allocated, so that a corrupt header cannot ask for more memory than the render uses.
+    pub fn read(mut reader: impl Read, size: (u32, u32)) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != Self::MAGIC {
+            return Err(invalid_data("not a checkpoint file"));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != Self::VERSION {
+            return Err(invalid_data(format!(
+                "checkpoint version {} instead of {}",
+                version,
+                Self::VERSION
+            )));
+        }
+
+        let mut scene_hash = [0; 8];
+        reader.read_exact(&mut scene_hash)?;
+        let checkpoint_size = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+        if checkpoint_size != size {
+            return Err(invalid_data(format!(
+                "checkpoint of a {}x{} render instead of {}x{}",
+                checkpoint_size.0, checkpoint_size.1, size.0, size.1
+            )));
+        }
+        let frame_index = read_u32(&mut reader)?;
+        let mode = match read_u32(&mut reader)? {
+            0 => TracerMode::Megakernel,
+            1 => TracerMode::Wavefront,
+            mode => return Err(invalid_data(format!("unknown tracer mode {}", mode))),
+        };
+        let mut elapsed = [0; 8];
+        reader.read_exact(&mut elapsed)?;
+
+        let mut render_param = RenderParam::zeroed();
+        reader.read_exact(bytemuck::bytes_of_mut(&mut render_param))?;
+
+        let pixel_count = (size.0 as usize)
+            .checked_mul(size.1 as usize)
+            .ok_or_else(|| invalid_data("checkpoint too large"))?;
+        let mut image = vec![[0.0; 3]; pixel_count];
+        reader.read_exact(bytemuck::cast_slice_mut(&mut image))?;
+        let mut pixel_stats = vec![GpuPixelStats::zeroed(); pixel_count];
+        reader.read_exact(bytemuck::cast_slice_mut(&mut pixel_stats))?;
+
+        Ok(Self {
+            scene_hash: u64::from_le_bytes(scene_hash),
+            size,
+            frame_index,
+            mode,
+            render_param,
+            elapsed: Duration::from_millis(u64::from_le_bytes(elapsed)),
+            image,
+            pixel_stats,
+        })
+    }
+
+    /// The mean of the samples of every pixel.
+    pub fn mean_image(&self) -> Vec<[f32; 3]> {
+        self.image
+            .iter()
+            .zip(&self.pixel_stats)
+            .map(|(sum, stats)| sum.map(|channel| channel / stats.samples.max(1) as f32))
+            .collect()
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> io::Result<()> {
+        // Written next to the target first so that a crash never leaves half a checkpoint
+        let partial = path.with_extension("partial");
+        let mut writer = io::BufWriter::new(std::fs::File::create(&partial)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        std::fs::rename(partial, path)
+    }
+
+    /// Loads a checkpoint of a `size` render, see [`Checkpoint::read`]. The length of the
+    /// file is checked first, a truncated checkpoint is rejected before anything is read.
+    pub fn load(path: &std::path::Path, size: (u32, u32)) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        match Self::file_len(size) {
+            Some(expected) if expected == len => Self::read(io::BufReader::new(file), size),
+            _ => Err(invalid_data(format!(
+                "{}: {} bytes, not a checkpoint of a {}x{} render",
+                path.display(),
+                len,
+                size.0,
+                size.1
+            ))),
+        }
+    }
+}
+
+/// FNV-1a hash of the geometry, materials, camera and aperture mask of `scene` rendered
+/// at `size`, stable across runs and platforms unlike `std::hash`.
+pub fn scene_hash(scene: &Scene, size: (u32, u32)) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let (materials, textures) = scene.gpu_materials();
+    let camera = GpuCamera::new(&scene.camera, size);
+    let aperture_mask = scene.gpu_aperture_mask();
+    let size = [size.0, size.1];
+    let parts: [&[u8]; 8] = [
+        bytemuck::cast_slice(&scene.objects),
+        bytemuck::cast_slice(&scene.spheres),
+        bytemuck::cast_slice(&scene.meshes),
+        bytemuck::cast_slice(&materials),
+        bytemuck::cast_slice(&textures),
+        bytemuck::bytes_of(&camera),
+        bytemuck::cast_slice(&aperture_mask),
+        bytemuck::cast_slice(&size),
+    ];
+
+    let mut hash = OFFSET_BASIS;
+    for part in parts {
+        // The length keeps the boundaries between the parts from moving unnoticed
+        for byte in (part.len() as u64).to_le_bytes().iter().chain(part) {
+            hash = (hash ^ *byte as u64).wrapping_mul(PRIME);
+        }
+    }
+    hash
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(size: (u32, u32)) -> Checkpoint {
+        let pixel_count = (size.0 * size.1) as usize;
+        Checkpoint {
+            scene_hash: 0x0123_4567_89ab_cdef,
+            size,
+            frame_index: 42,
+            mode: TracerMode::Wavefront,
+            render_param: RenderParam {
+                total_samples: 64,
+                seed: 7,
+                ..Default::default()
+            },
+            elapsed: Duration::from_millis(12_345),
+            image: (0..pixel_count).map(|i| [i as f32, 0.5, -1.0]).collect(),
+            pixel_stats: (0..pixel_count)
+                .map(|i| GpuPixelStats {
+                    luminance_sum: i as f32 * 0.5,
+                    luminance_sq_sum: i as f32 * 0.25,
+                    samples: 64 + i as u32,
+                    frame_samples: 1,
+                })
+                .collect(),
+        }
+    }
+
+    fn bytes(checkpoint: &Checkpoint) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let size = (5, 3);
+        let written = bytes(&checkpoint(size));
+        assert_eq!(Some(written.len() as u64), Checkpoint::file_len(size));
+
+        let read = Checkpoint::read(&written[..], size).unwrap();
+        assert_eq!(read.scene_hash, 0x0123_4567_89ab_cdef);
+        assert_eq!(read.size, size);
+        assert_eq!(read.frame_index, 42);
+        assert_eq!(read.mode, TracerMode::Wavefront);
+        assert_eq!(read.render_param, checkpoint(size).render_param);
+        assert_eq!(read.elapsed, Duration::from_millis(12_345));
+        assert_eq!(read.image, checkpoint(size).image);
+        assert_eq!(bytes(&read), written);
+    }
+
+    #[test]
+    fn rejects_corrupt_headers() {
+        let size = (5, 3);
+        let written = bytes(&checkpoint(size));
+        let corrupt = |offset: usize, value: &[u8]| {
+            let mut bytes = written.clone();
+            bytes[offset..offset + value.len()].copy_from_slice(value);
+            Checkpoint::read(&bytes[..], size)
+                .err()
+                .map(|error| error.kind())
+        };
+        let invalid = Some(io::ErrorKind::InvalidData);
+        assert_eq!(corrupt(0, b"PNG"), invalid);
+        assert_eq!(corrupt(8, &99_u32.to_le_bytes()), invalid);
+        // A size far beyond the memory of the machine is rejected before allocating
+        assert_eq!(corrupt(20, &u32::MAX.to_le_bytes()), invalid);
+        assert_eq!(corrupt(24, &u32::MAX.to_le_bytes()), invalid);
+        assert_eq!(corrupt(20, &6_u32.to_le_bytes()), invalid);
+        assert_eq!(corrupt(32, &2_u32.to_le_bytes()), invalid);
+
+        let truncated = Checkpoint::read(&written[..written.len() - 1], size);
+        assert_eq!(
+            truncated.err().map(|error| error.kind()),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+        assert!(Checkpoint::read(&written[..], (3, 5)).is_err());
+    }
+
+    #[test]
+    fn load_checks_the_file_length() {
+        let size = (5, 3);
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.bin", std::process::id()));
+        let mut written = bytes(&checkpoint(size));
+        written.pop();
+        std::fs::write(&path, &written).unwrap();
+        let error = Checkpoint::load(&path, size)
+            .err()
+            .map(|error| error.kind());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error, Some(io::ErrorKind::InvalidData));
+    }
+}
@@ -0,0 +1,533 @@
+#[cfg(not(target_arch = "wasm32"))]
+mod checkpoint;
+#[cfg(not(target_arch = "wasm32"))]
+pub use checkpoint::{scene_hash, Checkpoint};
+
+mod wavefront;
+use wavefront::Wavefront;
+
+use crate::{
+    object::{Mesh, Object, Sphere},
+    scene::{FrameBudget, FrameData, GpuCamera, GpuFilterTable, GpuMaterial, RenderParam, Scene},
+    utils::{GpuReadback, StorageBuffer, UniformBuffer},
+};
+
+/// The storage buffers of the scene bind group, mirrored from [`Scene`].
+struct SceneBuffers {
+    objects: StorageBuffer<Object>,
+    spheres: StorageBuffer<Sphere>,
+    materials: StorageBuffer<GpuMaterial>,
+    textures: StorageBuffer<[f32; 3]>,
+    surfaces: StorageBuffer<Mesh>,
+    aperture_mask: StorageBuffer<f32>,
+}
+
+impl SceneBuffers {
+    fn new(device: &wgpu::Device, scene: &Scene) -> Self {
+        let (material_data, global_texture_data) = scene.gpu_materials();
+        Self {
+            objects: StorageBuffer::new(device, &scene.objects, 0_u32, Some("objects buffer")),
+            spheres: StorageBuffer::new(device, &scene.spheres, 1_u32, Some("sphere buffer")),
+            materials: StorageBuffer::new(device, &material_data, 2_u32, Some("material buffer")),
+            textures: StorageBuffer::new(
+                device,
+                &global_texture_data,
+                3_u32,
+                Some("texture buffer"),
+            ),
+            surfaces: StorageBuffer::new(device, &scene.meshes, 4_u32, Some("surfaces buffer")),
+            aperture_mask: StorageBuffer::new(
+                device,
+                &scene.gpu_aperture_mask(),
+                5_u32,
+                Some("aperture mask buffer"),
+            ),
+        }
+    }
+
+    fn layout(&self, visibility: wgpu::ShaderStages) -> [wgpu::BindGroupLayoutEntry; 6] {
+        [
+            self.objects.layout(visibility, true),
+            self.spheres.layout(visibility, true),
+            self.materials.layout(visibility, true),
+            self.textures.layout(visibility, true),
+            self.surfaces.layout(visibility, true),
+            self.aperture_mask.layout(visibility, true),
+        ]
+    }
+
+    fn bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                self.objects.binding(),
+                self.spheres.binding(),
+                self.materials.binding(),
+                self.textures.binding(),
+                self.surfaces.binding(),
+                self.aperture_mask.binding(),
+            ],
+            label: Some("scene bind group"),
+        })
+    }
+
+    /// Uploads the parts of `scene` that changed. Returns `true` when a buffer was
+    /// reallocated or resized and the scene bind group must be rebuilt.
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) -> bool {
+        let (material_data, global_texture_data) = scene.gpu_materials();
+        self.objects.replace(&scene.objects);
+        self.spheres.replace(&scene.spheres);
+        self.materials.replace(&material_data);
+        self.textures.replace(&global_texture_data);
+        self.surfaces.replace(&scene.meshes);
+        self.aperture_mask.replace(&scene.gpu_aperture_mask());
+
+        let mut rebind = self.objects.sync(device, queue);
+        rebind |= self.spheres.sync(device, queue);
+        rebind |= self.materials.sync(device, queue);
+        rebind |= self.textures.sync(device, queue);
+        rebind |= self.surfaces.sync(device, queue);
+        rebind |= self.aperture_mask.sync(device, queue);
+        rebind
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TracerMode {
+    /// A single kernel traces whole paths, see `cs_main`.
+    Megakernel,
+    /// Separate kernels per path tracing stage, see wavefront.wgsl.
+    Wavefront,
+}
+
+/// Samples taken by a pixel and the sums of their luminance and squared luminance, the
+/// estimated error of the pixel drives adaptive sampling. Matches `PixelStats` in
+/// adaptive.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuPixelStats {
+    luminance_sum: f32,
+    luminance_sq_sum: f32,
+    samples: u32,
+    frame_samples: u32,
+}
+
+/// Traces the scene with a compute shader and accumulates the samples into the image buffer.
+///
+/// It does not depend on a surface, presenting the image buffer is left to the caller.
+pub struct PathTracer {
+    size: (u32, u32),
+    mode: TracerMode,
+    pipeline: wgpu::ComputePipeline,
+    adaptive_pipeline: wgpu::ComputePipeline,
+    wavefront: Option<Wavefront>,
+    camera_buffer: UniformBuffer<GpuCamera>,
+    frame_data_buffer: UniformBuffer<FrameData>,
+    render_param_buffer: UniformBuffer<RenderParam>,
+    image_buffer: StorageBuffer<[f32; 3]>,
+    pixel_stats_buffer: StorageBuffer<GpuPixelStats>,
+    filter_table_buffer: UniformBuffer<GpuFilterTable>,
+    /// Pixels above the noise threshold in the last frame, counted by `cs_adaptive`.
+    noisy_pixels_buffer: StorageBuffer<u32>,
+    noisy_pixels_readback: GpuReadback<u32>,
+    image_bind_group_layout: wgpu::BindGroupLayout,
+    image_bind_group: wgpu::BindGroup,
+    scene_buffers: SceneBuffers,
+    scene_bind_group_layout: wgpu::BindGroupLayout,
+    scene_bind_group: wgpu::BindGroup,
+}
+
+impl PathTracer {
+    /// Must match `@workgroup_size` of `cs_main` in the shader.
+    pub const WORKGROUP_SIZE: (u32, u32) = (8, 8);
+
+    /// Most pixels whose per-pixel storage buffers fit in a binding of `device`, the paths
+    /// of the wavefront tracer being the largest when it is available.
+    pub fn max_pixels(device: &wgpu::Device) -> u64 {
+        let limits = device.limits();
+        let mut pixel_bytes =
+            std::mem::size_of::<[f32; 3]>().max(std::mem::size_of::<GpuPixelStats>());
+        if limits.max_storage_buffers_per_shader_stage >= wavefront::STORAGE_BUFFERS {
+            pixel_bytes = pixel_bytes.max(wavefront::PATH_BYTES);
+        }
+        limits.max_storage_buffer_binding_size as u64 / pixel_bytes as u64
+    }
+
+    pub fn new(device: &wgpu::Device, scene: &Scene, size: (u32, u32)) -> Self {
+        let camera_buffer = UniformBuffer::new(
+            device,
+            GpuCamera::new(&scene.camera, size),
+            0_u32,
+            Some("camera buffer"),
+        );
+
+        let frame_data_buffer =
+            UniformBuffer::new(device, scene.frame_data, 1_u32, Some("frame data buffer"));
+
+        let render_param_buffer = UniformBuffer::new(
+            device,
+            scene.render_param,
+            2_u32,
+            Some("render param buffer"),
+        );
+
+        let (image_buffer, pixel_stats_buffer) = Self::create_image_buffers(device, size);
+
+        let filter_table_buffer = UniformBuffer::new(
+            device,
+            GpuFilterTable::new(),
+            5_u32,
+            Some("filter table buffer"),
+        );
+
+        let noisy_pixels_buffer =
+            StorageBuffer::new_zeroed(device, 1, 6_u32, Some("noisy pixels buffer"));
+
+        let image_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    camera_buffer.layout(wgpu::ShaderStages::COMPUTE),
+                    frame_data_buffer.layout(wgpu::ShaderStages::COMPUTE),
+                    render_param_buffer.layout(wgpu::ShaderStages::COMPUTE),
+                    image_buffer.layout(wgpu::ShaderStages::COMPUTE, false),
+                    pixel_stats_buffer.layout(wgpu::ShaderStages::COMPUTE, false),
+                    filter_table_buffer.layout(wgpu::ShaderStages::COMPUTE),
+                    noisy_pixels_buffer.layout(wgpu::ShaderStages::COMPUTE, false),
+                ],
+                label: Some("image layout"),
+            });
+
+        let image_bind_group = Self::create_image_bind_group(
+            device,
+            &image_bind_group_layout,
+            &camera_buffer,
+            &frame_data_buffer,
+            &render_param_buffer,
+            &image_buffer,
+            &pixel_stats_buffer,
+            &filter_table_buffer,
+            &noisy_pixels_buffer,
+        );
+
+        let scene_buffers = SceneBuffers::new(device, scene);
+
+        let scene_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &scene_buffers.layout(wgpu::ShaderStages::COMPUTE),
+                label: Some("scene layout"),
+            });
+
+        let scene_bind_group = scene_buffers.bind_group(device, &scene_bind_group_layout);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("raytracing.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("../shader/raytracing.wgsl"),
+                    include_str!("../shader/rng.wgsl"),
+                    include_str!("../shader/sampler.wgsl"),
+                    include_str!("../shader/adaptive.wgsl"),
+                    include_str!("../shader/filter.wgsl"),
+                    include_str!("../shader/firefly.wgsl"),
+                    include_str!("../shader/aperture.wgsl"),
+                    include_str!("../shader/motion.wgsl")
+                )
+                .into(),
+            ),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Path tracer Pipeline Layout"),
+            bind_group_layouts: &[&image_bind_group_layout, &scene_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |label: &str, entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+        let adaptive_pipeline = pipeline("Adaptive sampling pipeline", "cs_adaptive");
+        let pipeline = pipeline("Path tracer pipeline", "cs_main");
+
+        let wavefront =
+            if device.limits().max_storage_buffers_per_shader_stage >= wavefront::STORAGE_BUFFERS {
+                Some(Wavefront::new(
+                    device,
+                    scene,
+                    size,
+                    &image_bind_group_layout,
+                    &scene_bind_group_layout,
+                ))
+            } else {
+                log::warn!("Wavefront path tracing is not supported by this device");
+                None
+            };
+
+        Self {
+            size,
+            mode: TracerMode::Megakernel,
+            pipeline,
+            adaptive_pipeline,
+            wavefront,
+            camera_buffer,
+            frame_data_buffer,
+            render_param_buffer,
+            image_buffer,
+            pixel_stats_buffer,
+            filter_table_buffer,
+            noisy_pixels_buffer,
+            noisy_pixels_readback: GpuReadback::new(device, Some("noisy pixels readback buffer")),
+            image_bind_group_layout,
+            image_bind_group,
+            scene_buffers,
+            scene_bind_group_layout,
+            scene_bind_group,
+        }
+    }
+
+    fn create_image_buffers(
+        device: &wgpu::Device,
+        size: (u32, u32),
+    ) -> (StorageBuffer<[f32; 3]>, StorageBuffer<GpuPixelStats>) {
+        let pixel_count = size.0 as usize * size.1 as usize;
+        (
+            StorageBuffer::new_zeroed(device, pixel_count, 3_u32, Some("image buffer")),
+            StorageBuffer::new_zeroed(device, pixel_count, 4_u32, Some("pixel stats buffer")),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_image_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        camera_buffer: &UniformBuffer<GpuCamera>,
+        frame_data_buffer: &UniformBuffer<FrameData>,
+        render_param_buffer: &UniformBuffer<RenderParam>,
+        image_buffer: &StorageBuffer<[f32; 3]>,
+        pixel_stats_buffer: &StorageBuffer<GpuPixelStats>,
+        filter_table_buffer: &UniformBuffer<GpuFilterTable>,
+        noisy_pixels_buffer: &StorageBuffer<u32>,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                camera_buffer.binding(),
+                frame_data_buffer.binding(),
+                render_param_buffer.binding(),
+                image_buffer.binding(),
+                pixel_stats_buffer.binding(),
+                filter_table_buffer.binding(),
+                noisy_pixels_buffer.binding(),
+            ],
+            label: Some("image bind group"),
+        })
+    }
+
+    pub fn mode(&self) -> TracerMode {
+        self.mode
+    }
+
+    /// Most samples per pixel a frame of the current mode can ask for with `render_param`,
+    /// see [`FrameBudget::set_limit`].
+    pub fn samples_per_frame_limit(&self, render_param: &RenderParam) -> u32 {
+        match self.mode {
+            TracerMode::Megakernel => FrameBudget::MAX_SAMPLES_PER_FRAME,
+            TracerMode::Wavefront => Wavefront::MAX_SAMPLES_PER_FRAME / render_param.boost(),
+        }
+    }
+
+    /// Switches the tracing kernels, falls back to the megakernel when wavefront is unavailable.
+    pub fn set_mode(&mut self, mode: TracerMode) {
+        self.mode = match mode {
+            TracerMode::Wavefront if self.wavefront.is_none() => TracerMode::Megakernel,
+            mode => mode,
+        };
+    }
+
+    pub fn frame_data_buffer(&self) -> &UniformBuffer<FrameData> {
+        &self.frame_data_buffer
+    }
+
+    pub fn render_param_buffer(&self) -> &UniformBuffer<RenderParam> {
+        &self.render_param_buffer
+    }
+
+    pub fn image_buffer(&self) -> &StorageBuffer<[f32; 3]> {
+        &self.image_buffer
+    }
+
+    pub fn pixel_stats_buffer(&self) -> &StorageBuffer<GpuPixelStats> {
+        &self.pixel_stats_buffer
+    }
+
+    /// Reallocates the image buffers for the new size, the accumulation is lost.
+    pub fn resize(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        (self.image_buffer, self.pixel_stats_buffer) = Self::create_image_buffers(device, size);
+        self.image_bind_group = Self::create_image_bind_group(
+            device,
+            &self.image_bind_group_layout,
+            &self.camera_buffer,
+            &self.frame_data_buffer,
+            &self.render_param_buffer,
+            &self.image_buffer,
+            &self.pixel_stats_buffer,
+            &self.filter_table_buffer,
+            &self.noisy_pixels_buffer,
+        );
+        if let Some(wavefront) = &mut self.wavefront {
+            wavefront.resize(device, size);
+        }
+    }
+
+    /// Uploads the scene geometry and materials that changed since the last upload.
+    pub fn upload_scene(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        if self.scene_buffers.upload(device, queue, scene) {
+            self.scene_bind_group = self
+                .scene_buffers
+                .bind_group(device, &self.scene_bind_group_layout);
+        }
+        if let Some(wavefront) = &mut self.wavefront {
+            wavefront.upload_lights(device, queue, scene);
+        }
+    }
+
+    /// Copies the accumulation back from the GPU, blocking until it is done.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn checkpoint(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        elapsed: std::time::Duration,
+    ) -> Checkpoint {
+        Checkpoint {
+            scene_hash: scene_hash(scene, self.size),
+            size: self.size,
+            frame_index: scene.frame_data.index,
+            mode: self.mode,
+            render_param: scene.render_param,
+            elapsed,
+            image: self.image_buffer.read_back(device, queue),
+            pixel_stats: self.pixel_stats_buffer.read_back(device, queue),
+        }
+    }
+
+    /// Continues the accumulation of `checkpoint`, its render parameters and frame index
+    /// replace the ones of `scene`. Fails when it was taken from another scene or size.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn restore(
+        &mut self,
+        queue: &wgpu::Queue,
+        scene: &mut Scene,
+        checkpoint: &Checkpoint,
+    ) -> std::io::Result<()> {
+        let invalid =
+            |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+        if checkpoint.size != self.size {
+            return Err(invalid("the checkpoint was rendered at another size"));
+        }
+        if checkpoint.scene_hash != scene_hash(scene, self.size) {
+            return Err(invalid("the checkpoint was rendered from another scene"));
+        }
+        if checkpoint.mode == TracerMode::Wavefront && self.wavefront.is_none() {
+            return Err(invalid("the checkpoint needs the wavefront tracer"));
+        }
+
+        self.mode = checkpoint.mode;
+        self.image_buffer.write_unmirrored(queue, &checkpoint.image);
+        self.pixel_stats_buffer
+            .write_unmirrored(queue, &checkpoint.pixel_stats);
+        scene.render_param = checkpoint.render_param;
+        scene.frame_data.index = checkpoint.frame_index;
+        Ok(())
+    }
+
+    /// Writes the camera, frame data and render parameters of `scene` for the next dispatch.
+    pub fn update_uniforms(&mut self, queue: &wgpu::Queue, scene: &Scene) {
+        self.camera_buffer
+            .set(GpuCamera::new(&scene.camera, self.size));
+        self.camera_buffer.sync(queue);
+
+        self.frame_data_buffer.set(scene.frame_data);
+        self.frame_data_buffer.sync(queue);
+
+        self.render_param_buffer.set(scene.render_param);
+        self.render_param_buffer.sync(queue);
+    }
+
+    /// Records one frame of samples, `samples_per_pixel` for every pixel unless adaptive
+    /// sampling decides otherwise.
+    pub fn dispatch(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites<'_>>,
+    ) {
+        encoder.clear_buffer(self.noisy_pixels_buffer.handle(), 0, None);
+        self.record_pass(encoder, timestamp_writes);
+        self.noisy_pixels_readback
+            .copy(encoder, self.noisy_pixels_buffer.handle());
+    }
+
+    /// Whether the next dispatch counts noisy pixels for reading back, it does not while
+    /// a previous count is still in flight.
+    pub fn counts_noisy_pixels(&self) -> bool {
+        self.noisy_pixels_readback.is_idle()
+    }
+
+    /// Starts reading back the noisy pixel count, to call once the frame is submitted.
+    pub fn after_submit(&mut self) {
+        self.noisy_pixels_readback.after_submit();
+    }
+
+    /// Pixels above the noise threshold in a recent frame, once read back.
+    pub fn read_noisy_pixels(&mut self, device: &wgpu::Device) -> Option<u32> {
+        self.noisy_pixels_readback.read(device)
+    }
+
+    fn record_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites<'_>>,
+    ) {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Path tracer Pass"),
+            timestamp_writes,
+        });
+
+        compute_pass.set_bind_group(0, &self.image_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.scene_bind_group, &[]);
+
+        // Decides how many samples each pixel takes, and clears the image if needed
+        let pixel_workgroups = (
+            self.size.0.div_ceil(Self::WORKGROUP_SIZE.0),
+            self.size.1.div_ceil(Self::WORKGROUP_SIZE.1),
+        );
+        compute_pass.set_pipeline(&self.adaptive_pipeline);
+        compute_pass.dispatch_workgroups(pixel_workgroups.0, pixel_workgroups.1, 1);
+
+        match (self.mode, &self.wavefront) {
+            (TracerMode::Wavefront, Some(wavefront)) => {
+                let render_param = self.render_param_buffer.get();
+                wavefront.dispatch(
+                    &mut compute_pass,
+                    render_param.max_samples_per_frame(),
+                    render_param.max_depth,
+                );
+            }
+            _ => {
+                compute_pass.set_pipeline(&self.pipeline);
+                compute_pass.dispatch_workgroups(pixel_workgroups.0, pixel_workgroups.1, 1);
+            }
+        }
+    }
+}
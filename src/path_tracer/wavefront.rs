@@ -0,0 +1,315 @@
+use crate::{
+    scene::{GpuLight, Scene},
+    utils::StorageBuffer,
+};
+
+use super::PathTracer;
+
+/// Opaque mirror of `PathState` in wavefront.wgsl, only used for sizing.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuPathState {
+    data: [[u32; 4]; 10],
+}
+
+/// Number of `u32` in `Counters` in wavefront.wgsl.
+const COUNTERS_LEN: usize = 7;
+/// Two ray queues for ping-ponging between bounces, one per shaded material and the shadow rays.
+const QUEUES_PER_PATH: usize = 6;
+
+/// Indices in the indirect dispatch arguments written by `wf_dispatch_args`.
+const ARGS_INTERSECT: usize = 0;
+const ARGS_SHADE: [usize; 3] = [1, 2, 3];
+const ARGS_SHADOW: usize = 4;
+const ARGS_LEN: usize = 5;
+
+/// Storage buffers per shader stage used by the wavefront pipelines, the scene and
+/// image bind groups included.
+pub const STORAGE_BUFFERS: u32 = 14;
+
+/// Bytes of the largest per-path storage buffer, the path states.
+pub const PATH_BYTES: usize = {
+    let state = std::mem::size_of::<GpuPathState>();
+    let queues = QUEUES_PER_PATH * std::mem::size_of::<u32>();
+    if state > queues {
+        state
+    } else {
+        queues
+    }
+};
+
+/// Wavefront path tracer: ray generation, intersection, shading per material type,
+/// shadow rays and accumulation run as separate kernels connected by queues.
+pub struct Wavefront {
+    size: (u32, u32),
+    paths: StorageBuffer<GpuPathState>,
+    counters: StorageBuffer<u32>,
+    queues: StorageBuffer<u32>,
+    lights: StorageBuffer<GpuLight>,
+    dispatch_args: StorageBuffer<[u32; 3]>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    args_bind_group: wgpu::BindGroup,
+    reset: wgpu::ComputePipeline,
+    generate: wgpu::ComputePipeline,
+    intersect: wgpu::ComputePipeline,
+    shade: [wgpu::ComputePipeline; 3],
+    shadow: wgpu::ComputePipeline,
+    next_bounce: wgpu::ComputePipeline,
+    accumulate: wgpu::ComputePipeline,
+    compute_args: wgpu::ComputePipeline,
+}
+
+impl Wavefront {
+    /// Most samples a pixel takes in one frame. Each sample records a dozen dispatches
+    /// per bounce, and browsers and drivers give up on passes holding too many of them.
+    pub const MAX_SAMPLES_PER_FRAME: u32 = 8;
+
+    pub fn new(
+        device: &wgpu::Device,
+        scene: &Scene,
+        size: (u32, u32),
+        image_bind_group_layout: &wgpu::BindGroupLayout,
+        scene_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let (paths, queues) = Self::create_path_buffers(device, size);
+        let counters = StorageBuffer::new_zeroed(device, COUNTERS_LEN, 1_u32, Some("counters"));
+        let lights = StorageBuffer::new(device, &scene.gpu_lights(), 3_u32, Some("lights"));
+        let dispatch_args = StorageBuffer::new_zeroed_with_usage(
+            device,
+            ARGS_LEN,
+            wgpu::BufferUsages::INDIRECT,
+            0_u32,
+            Some("dispatch args"),
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                paths.layout(wgpu::ShaderStages::COMPUTE, false),
+                counters.layout(wgpu::ShaderStages::COMPUTE, false),
+                queues.layout(wgpu::ShaderStages::COMPUTE, false),
+                lights.layout(wgpu::ShaderStages::COMPUTE, true),
+            ],
+            label: Some("wavefront layout"),
+        });
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &paths,
+            &counters,
+            &queues,
+            &lights,
+        );
+
+        let args_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[dispatch_args.layout(wgpu::ShaderStages::COMPUTE, false)],
+                label: Some("wavefront args layout"),
+            });
+
+        let args_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &args_bind_group_layout,
+            entries: &[dispatch_args.binding()],
+            label: Some("wavefront args bind group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("wavefront.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("../shader/raytracing.wgsl"),
+                    include_str!("../shader/rng.wgsl"),
+                    include_str!("../shader/sampler.wgsl"),
+                    include_str!("../shader/adaptive.wgsl"),
+                    include_str!("../shader/filter.wgsl"),
+                    include_str!("../shader/firefly.wgsl"),
+                    include_str!("../shader/aperture.wgsl"),
+                    include_str!("../shader/motion.wgsl"),
+                    include_str!("../shader/wavefront.wgsl")
+                )
+                .into(),
+            ),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Wavefront Pipeline Layout"),
+            bind_group_layouts: &[
+                image_bind_group_layout,
+                scene_bind_group_layout,
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        // The dispatch arguments are written as storage and read as indirect buffer, they
+        // must not be bound while the other kernels run, hence the separate layout.
+        let args_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Wavefront args Pipeline Layout"),
+            bind_group_layouts: &[
+                image_bind_group_layout,
+                scene_bind_group_layout,
+                &bind_group_layout,
+                &args_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = |layout: &wgpu::PipelineLayout, entry_point: &str| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+
+        Self {
+            size,
+            reset: pipeline(&pipeline_layout, "wf_reset"),
+            generate: pipeline(&pipeline_layout, "wf_generate"),
+            intersect: pipeline(&pipeline_layout, "wf_intersect"),
+            shade: [
+                pipeline(&pipeline_layout, "wf_shade_lambertian"),
+                pipeline(&pipeline_layout, "wf_shade_metal"),
+                pipeline(&pipeline_layout, "wf_shade_dielectric"),
+            ],
+            shadow: pipeline(&pipeline_layout, "wf_shadow"),
+            next_bounce: pipeline(&pipeline_layout, "wf_next_bounce"),
+            accumulate: pipeline(&pipeline_layout, "wf_accumulate"),
+            compute_args: pipeline(&args_pipeline_layout, "wf_dispatch_args"),
+            paths,
+            counters,
+            queues,
+            lights,
+            dispatch_args,
+            bind_group_layout,
+            bind_group,
+            args_bind_group,
+        }
+    }
+
+    fn create_path_buffers(
+        device: &wgpu::Device,
+        size: (u32, u32),
+    ) -> (StorageBuffer<GpuPathState>, StorageBuffer<u32>) {
+        let path_count = size.0 as usize * size.1 as usize;
+        (
+            StorageBuffer::new_zeroed(device, path_count, 0_u32, Some("paths")),
+            StorageBuffer::new_zeroed(device, QUEUES_PER_PATH * path_count, 2_u32, Some("queues")),
+        )
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        paths: &StorageBuffer<GpuPathState>,
+        counters: &StorageBuffer<u32>,
+        queues: &StorageBuffer<u32>,
+        lights: &StorageBuffer<GpuLight>,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                paths.binding(),
+                counters.binding(),
+                queues.binding(),
+                lights.binding(),
+            ],
+            label: Some("wavefront bind group"),
+        })
+    }
+
+    fn rebuild_bind_group(&mut self, device: &wgpu::Device) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.paths,
+            &self.counters,
+            &self.queues,
+            &self.lights,
+        );
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        (self.paths, self.queues) = Self::create_path_buffers(device, size);
+        self.rebuild_bind_group(device);
+    }
+
+    pub fn upload_lights(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, scene: &Scene) {
+        self.lights.replace(&scene.gpu_lights());
+        if self.lights.sync(device, queue) {
+            self.rebuild_bind_group(device);
+        }
+    }
+
+    /// Records `samples_per_pixel` wavefront passes of `max_depth` bounces each, pixels
+    /// that need fewer samples this frame skip the remaining passes.
+    /// Group 0 and 1 must be the image and scene bind groups of the [`PathTracer`].
+    pub fn dispatch(
+        &self,
+        compute_pass: &mut wgpu::ComputePass<'_>,
+        samples_per_pixel: u32,
+        max_depth: u32,
+    ) {
+        let args_offset = |index: usize| (index * std::mem::size_of::<[u32; 3]>()) as u64;
+        let pixel_workgroups = (
+            self.size.0.div_ceil(PathTracer::WORKGROUP_SIZE.0),
+            self.size.1.div_ceil(PathTracer::WORKGROUP_SIZE.1),
+        );
+
+        compute_pass.set_bind_group(2, &self.bind_group, &[]);
+        compute_pass.set_bind_group(3, &self.args_bind_group, &[]);
+
+        for _ in 0..samples_per_pixel.min(Self::MAX_SAMPLES_PER_FRAME) {
+            compute_pass.set_pipeline(&self.reset);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+
+            compute_pass.set_pipeline(&self.generate);
+            compute_pass.dispatch_workgroups(pixel_workgroups.0, pixel_workgroups.1, 1);
+
+            for _ in 0..max_depth {
+                compute_pass.set_pipeline(&self.compute_args);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+
+                compute_pass.set_pipeline(&self.intersect);
+                compute_pass.dispatch_workgroups_indirect(
+                    self.dispatch_args.handle(),
+                    args_offset(ARGS_INTERSECT),
+                );
+
+                compute_pass.set_pipeline(&self.compute_args);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+
+                for (pipeline, args) in self.shade.iter().zip(ARGS_SHADE) {
+                    compute_pass.set_pipeline(pipeline);
+                    compute_pass.dispatch_workgroups_indirect(
+                        self.dispatch_args.handle(),
+                        args_offset(args),
+                    );
+                }
+
+                compute_pass.set_pipeline(&self.compute_args);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+
+                compute_pass.set_pipeline(&self.shadow);
+                compute_pass.dispatch_workgroups_indirect(
+                    self.dispatch_args.handle(),
+                    args_offset(ARGS_SHADOW),
+                );
+
+                compute_pass.set_pipeline(&self.next_bounce);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+            }
+
+            compute_pass.set_pipeline(&self.accumulate);
+            compute_pass.dispatch_workgroups(pixel_workgroups.0, pixel_workgroups.1, 1);
+        }
+    }
+}
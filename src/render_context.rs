@@ -1,15 +1,25 @@
 use egui_wgpu::ScreenDescriptor;
 use wgpu::util::DeviceExt;
 use winit::{
-    event::{DeviceEvent, WindowEvent},
+    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, WindowEvent},
+    keyboard::PhysicalKey,
     window::Window,
 };
 
 use crate::{
-    scene::{GpuCamera, GpuMaterial, Scene},
-    utils::{EguiRenderer, StorageBuffer, UniformBuffer, Vertex},
+    path_tracer::{PathTracer, TracerMode},
+    scene::{
+        Action, Aperture, Bookmark, CameraAnimation, CameraTransition, ControllerMode, Converged,
+        FrameBudget, FrameBudgetMode, InputMap, Keyframe, Material, PhysicalCamera, Pick,
+        PixelFilter, Progress, Projection, RenderParam, RenderState, SamplerKind, Scene,
+        StopCriteria, Texture,
+    },
+    utils::{EguiRenderer, GpuTimer, Vertex},
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use crate::path_tracer::Checkpoint;
+
 pub struct RenderContext<'a> {
     surface: wgpu::Surface<'a>,
     device: wgpu::Device,
@@ -17,17 +27,78 @@ pub struct RenderContext<'a> {
     config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
     window: &'a Window,
+    path_tracer: PathTracer,
     render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
-    image_bind_group: wgpu::BindGroup,
-    camera_buffer: UniformBuffer,
-    render_param_buffer: UniformBuffer,
-    frame_data_buffer: UniformBuffer,
-    scene_bind_group: wgpu::BindGroup,
+    display_bind_group_layout: wgpu::BindGroupLayout,
+    display_bind_group: wgpu::BindGroup,
     scene: Scene,
     latest_scene: Scene,
     pub egui_renderer: EguiRenderer,
     pub fps: f64,
+    frame_budget: FrameBudget,
+    gpu_timer: Option<GpuTimer>,
+    /// Samples per pixel of the pass being timed by `gpu_timer`.
+    timed_samples: u32,
+    frame_time: std::time::Duration,
+    progress: Progress,
+    /// `Progress::restarts` when the noisy pixel count being read back was dispatched.
+    counted_restarts: u32,
+    converged: Option<Converged>,
+    #[cfg(not(target_arch = "wasm32"))]
+    checkpoint_path: String,
+    /// Saves a checkpoint when the app closes, see [`RenderContext::on_exit`].
+    #[cfg(not(target_arch = "wasm32"))]
+    save_on_exit: bool,
+    /// Outcome of the last checkpoint save or load, shown in the UI.
+    #[cfg(not(target_arch = "wasm32"))]
+    checkpoint_status: String,
+    #[cfg(not(target_arch = "wasm32"))]
+    checkpoint_request: Option<CheckpointRequest>,
+    /// Whether the browser was seen holding the pointer lock since it was requested,
+    /// see [`RenderContext::pointer_lock_lost`].
+    #[cfg(target_arch = "wasm32")]
+    pointer_locked: bool,
+    /// Last position of the mouse over the window, in physical pixels.
+    cursor_position: glm::Vec2,
+    /// Object of `scene.objects` last clicked, framed with F.
+    selected_object: Option<usize>,
+    /// Keyframes recorded from the view, rendered as an image sequence by the headless
+    /// mode with `--camera-path`.
+    animation: CameraAnimation,
+    /// Time at which the next keyframe is recorded, in seconds.
+    keyframe_time: f32,
+    /// Time of the animation previewed in the view, advancing while playing.
+    animation_time: f32,
+    animation_playing: bool,
+    #[cfg(not(target_arch = "wasm32"))]
+    animation_path: String,
+    /// Outcome of the last keyframe save or load, shown in the UI.
+    #[cfg(not(target_arch = "wasm32"))]
+    animation_status: String,
+    /// Move to the bookmark recalled last, until the camera gets there.
+    camera_transition: Option<CameraTransition>,
+    /// Index in `scene.bookmarks` of the bookmark recalled or added last.
+    current_bookmark: Option<usize>,
+    /// Name of the next bookmark added.
+    bookmark_name: String,
+    /// Shows the window listing the controls.
+    show_help: bool,
+    /// Config file of the controls, loaded at startup when there is one.
+    #[cfg(not(target_arch = "wasm32"))]
+    controls_path: String,
+    /// Outcome of the last controls save or load, shown in the UI.
+    #[cfg(not(target_arch = "wasm32"))]
+    controls_status: String,
+}
+
+/// Checkpoint operation asked from the UI, handled at the start of the next frame so
+/// that the accumulation, sample count and frame index saved or restored agree.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug)]
+enum CheckpointRequest {
+    Save,
+    Load,
 }
 
 // const RGB_TRIANGLE: &[Vertex] = &[
@@ -55,18 +126,22 @@ const VERTICES: &[Vertex] = &[
 
 const VERTICES_LEN: usize = VERTICES.len();
 
+/// Config file of the controls read at startup, see [`InputMap`].
+#[cfg(not(target_arch = "wasm32"))]
+const CONTROLS_PATH: &str = "controls.txt";
+
 impl<'a> RenderContext<'a> {
     pub async fn new(window: &'a Window, scene: &Scene) -> RenderContext<'a> {
-        let size;
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {
-                use winit::platform::web::WindowExtWebSys;
-                let canvas = window.canvas().unwrap();
-                size = winit::dpi::PhysicalSize::new(canvas.client_width() as u32, canvas.client_height() as u32);
-            } else {
-                size = window.inner_size();
-            }
-        }
+        // The canvas is laid out in CSS pixels, rendered at the resolution of the screen
+        #[cfg(target_arch = "wasm32")]
+        let size = {
+            use winit::platform::web::WindowExtWebSys;
+            let canvas = window.canvas().unwrap();
+            winit::dpi::LogicalSize::new(canvas.client_width(), canvas.client_height())
+                .to_physical::<u32>(window.scale_factor())
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let size = window.inner_size();
 
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
@@ -88,17 +163,27 @@ impl<'a> RenderContext<'a> {
             .unwrap();
 
         log::debug!("Adapter: {:?}", adapter.get_info());
+        // Ask for what the wavefront tracer needs when available, see `PathTracer::new`
+        let max_storage_buffers_per_shader_stage = adapter
+            .limits()
+            .max_storage_buffers_per_shader_stage
+            .min(16);
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
+                    // Used to time the path tracer when available, see `GpuTimer`
+                    required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web, we'll have to disable some.
                     required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::default().using_resolution(adapter.limits())
+                        wgpu::Limits {
+                            max_storage_buffers_per_shader_stage,
+                            ..wgpu::Limits::default().using_resolution(adapter.limits())
+                        }
                     } else {
                         wgpu::Limits {
                             max_storage_buffer_binding_size: 512_u32 << 20,
+                            max_storage_buffers_per_shader_stage,
                             ..Default::default()
                         }
                     },
@@ -110,142 +195,33 @@ impl<'a> RenderContext<'a> {
             .await
             .unwrap();
         log::debug!("Device: {:?}", device);
+        let size = fit_texture_size(size, &device);
 
-        let camera_buffer = {
-            let camera = GpuCamera::new(&scene.camera, (size.width, size.height));
-
-            UniformBuffer::new_from_bytes(
-                &device,
-                bytemuck::bytes_of(&camera),
-                0_u32,
-                Some("camera buffer"),
-            )
-        };
-
-        let frame_data_buffer = {
-            let frame_data = scene.frame_data;
-            UniformBuffer::new_from_bytes(
-                &device,
-                bytemuck::bytes_of(&frame_data),
-                1_u32,
-                Some("frame data buffer"),
-            )
-        };
-
-        let render_param_buffer = {
-            UniformBuffer::new_from_bytes(
-                &device,
-                bytemuck::bytes_of(&scene.render_param),
-                2_u32,
-                Some("render param buffer"),
-            )
-        };
-        let (image_bind_group, image_bind_group_layout) = {
-            let image_buffer = {
-                let buffer = vec![[0_f32; 3]; size.width as usize * size.height as usize];
-                StorageBuffer::new_from_bytes(
-                    &device,
-                    bytemuck::cast_slice(buffer.as_slice()),
-                    3_u32,
-                    Some("image buffer"),
-                )
-            };
-
-            let image_bind_group_layout =
-                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[
-                        camera_buffer.layout(wgpu::ShaderStages::FRAGMENT),
-                        frame_data_buffer.layout(wgpu::ShaderStages::FRAGMENT),
-                        render_param_buffer.layout(wgpu::ShaderStages::FRAGMENT),
-                        image_buffer.layout(wgpu::ShaderStages::FRAGMENT, false),
-                    ],
-                    label: Some("image layout"),
-                });
-
-            let image_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &image_bind_group_layout,
-                entries: &[
-                    camera_buffer.binding(),
-                    frame_data_buffer.binding(),
-                    render_param_buffer.binding(),
-                    image_buffer.binding(),
-                ],
-                label: Some("image bind group"),
-            });
-
-            (image_bind_group, image_bind_group_layout)
-        };
-
-        let (scene_bind_group_layout, scene_bind_group) = {
-            let objects_buffer = StorageBuffer::new_from_bytes(
-                &device,
-                bytemuck::cast_slice(scene.objects.as_slice()),
-                0_u32,
-                Some("objects buffer"),
-            );
-
-            let sphere_buffer = StorageBuffer::new_from_bytes(
-                &device,
-                bytemuck::cast_slice(scene.spheres.as_slice()),
-                1_u32,
-                Some("sphere buffer"),
-            );
-
-            let mut global_texture_data = Vec::new();
-            let mut material_data: Vec<GpuMaterial> = Vec::with_capacity(scene.materials.len());
-            for material in scene.materials.iter() {
-                material_data.push(GpuMaterial::new(material, &mut global_texture_data));
-            }
-
-            let material_buffer = StorageBuffer::new_from_bytes(
-                &device,
-                bytemuck::cast_slice(material_data.as_slice()),
-                2_u32,
-                Some("material buffer"),
-            );
+        let path_tracer = PathTracer::new(&device, scene, (size.width, size.height));
 
-            let texture_buffer = StorageBuffer::new_from_bytes(
-                &device,
-                bytemuck::cast_slice(global_texture_data.as_slice()),
-                3_u32,
-                Some("texture buffer"),
-            );
-
-            let surfaces_buffer = StorageBuffer::new_from_bytes(
-                &device,
-                bytemuck::cast_slice(scene.meshes.as_slice()),
-                4_u32,
-                Some("surfaces buffer"),
-            );
-
-            let scene_bind_group_layout =
-                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[
-                        objects_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
-                        sphere_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
-                        material_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
-                        texture_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
-                        surfaces_buffer.layout(wgpu::ShaderStages::FRAGMENT, true),
-                    ],
-                    label: Some("scene layout"),
-                });
-
-            let scene_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                layout: &scene_bind_group_layout,
+        let display_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
-                    objects_buffer.binding(),
-                    sphere_buffer.binding(),
-                    material_buffer.binding(),
-                    texture_buffer.binding(),
-                    surfaces_buffer.binding(),
+                    path_tracer
+                        .frame_data_buffer()
+                        .layout(wgpu::ShaderStages::FRAGMENT),
+                    path_tracer
+                        .render_param_buffer()
+                        .layout(wgpu::ShaderStages::FRAGMENT),
+                    path_tracer
+                        .image_buffer()
+                        .layout(wgpu::ShaderStages::FRAGMENT, true),
+                    path_tracer
+                        .pixel_stats_buffer()
+                        .layout(wgpu::ShaderStages::FRAGMENT, true),
                 ],
-                label: Some("scene bind group"),
+                label: Some("display layout"),
             });
 
-            (scene_bind_group_layout, scene_bind_group)
-        };
+        let display_bind_group =
+            Self::create_display_bind_group(&device, &display_bind_group_layout, &path_tracer);
 
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shader/raytracing.wgsl"));
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shader/display.wgsl"));
 
         let surface_caps = surface.get_capabilities(&adapter);
         // Shader code in this tutorial assumes an sRGB surface texture. Using a different
@@ -276,7 +252,7 @@ impl<'a> RenderContext<'a> {
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&image_bind_group_layout, &scene_bind_group_layout],
+                bind_group_layouts: &[&display_bind_group_layout],
                 push_constant_ranges: &[],
             });
 
@@ -333,6 +309,24 @@ impl<'a> RenderContext<'a> {
 
         let egui_renderer = EguiRenderer::new(&device, config.format, None, 1, window);
 
+        let gpu_timer = GpuTimer::new(&device, &queue);
+        if gpu_timer.is_none() {
+            log::info!("Timestamp queries unsupported, samples per frame follow the frame time");
+        }
+
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut scene = scene.clone();
+        // Saved from the controls window, the default controls stay without one
+        #[cfg(not(target_arch = "wasm32"))]
+        match InputMap::load(std::path::Path::new(CONTROLS_PATH)) {
+            Ok(input_map) => {
+                log::info!("Controls loaded from {}", CONTROLS_PATH);
+                scene.camera_controller.input_map = input_map;
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => log::warn!("Ignoring {}: {}", CONTROLS_PATH, error),
+        }
+
         Self {
             surface,
             device,
@@ -340,34 +334,238 @@ impl<'a> RenderContext<'a> {
             config,
             size,
             window,
+            path_tracer,
             render_pipeline,
             vertex_buffer,
-            image_bind_group,
-            camera_buffer,
-            frame_data_buffer,
-            render_param_buffer,
-            scene_bind_group,
+            display_bind_group_layout,
+            display_bind_group,
             scene: scene.clone(),
             latest_scene: scene.clone(),
             egui_renderer,
             fps: 0.0,
+            frame_budget: FrameBudget::new(
+                FrameBudgetMode::Interactive,
+                std::time::Duration::from_millis(16),
+            ),
+            gpu_timer,
+            timed_samples: 0,
+            frame_time: std::time::Duration::ZERO,
+            progress: Progress::new(StopCriteria {
+                max_time: None,
+                noise: false,
+            }),
+            counted_restarts: 0,
+            converged: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            checkpoint_path: String::from("render.ckpt"),
+            #[cfg(not(target_arch = "wasm32"))]
+            save_on_exit: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            checkpoint_status: String::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            checkpoint_request: None,
+            #[cfg(target_arch = "wasm32")]
+            pointer_locked: false,
+            cursor_position: glm::vec2(0.0, 0.0),
+            selected_object: None,
+            animation: CameraAnimation::default(),
+            keyframe_time: 0.0,
+            animation_time: 0.0,
+            animation_playing: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            animation_path: String::from("camera_path.txt"),
+            #[cfg(not(target_arch = "wasm32"))]
+            animation_status: String::new(),
+            camera_transition: None,
+            current_bookmark: None,
+            bookmark_name: String::new(),
+            show_help: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            controls_path: String::from(CONTROLS_PATH),
+            #[cfg(not(target_arch = "wasm32"))]
+            controls_status: String::new(),
         }
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
+    fn create_display_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        path_tracer: &PathTracer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                path_tracer.frame_data_buffer().binding(),
+                path_tracer.render_param_buffer().binding(),
+                path_tracer.image_buffer().binding(),
+                path_tracer.pixel_stats_buffer().binding(),
+            ],
+            label: Some("display bind group"),
+        })
+    }
+
+    /// Reconfigures the surface and reallocates every buffer whose size depends
+    /// on the viewport. Returns `false` when the window is minimised (zero sized),
+    /// in which case nothing is touched and rendering should be skipped.
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) -> bool {
+        if new_size.width == 0 || new_size.height == 0 {
+            return false;
+        }
+        let new_size = fit_texture_size(new_size, &self.device);
+
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+        self.surface.configure(&self.device, &self.config);
+
+        if new_size != self.size {
             self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            self.path_tracer
+                .resize(&self.device, (new_size.width, new_size.height));
+            self.display_bind_group = Self::create_display_bind_group(
+                &self.device,
+                &self.display_bind_group_layout,
+                &self.path_tracer,
+            );
+
+            // The old accumulation does not map onto the new pixel grid
+            self.scene.frame_data.width = new_size.width;
+            self.scene.frame_data.height = new_size.height;
+            self.scene.render_param.total_samples = 0;
         }
+
+        true
     }
 
     pub fn window_event(&mut self, event: &WindowEvent, mouse_pressed: &mut bool) {
-        self.egui_renderer.handle_input(self.window, event);
+        let consumed = self.egui_renderer.handle_input(self.window, event);
+        #[cfg(target_arch = "wasm32")]
+        let rotating = *mouse_pressed;
         self.scene
             .camera_controller
             .handle_input(event, mouse_pressed);
+        #[cfg(target_arch = "wasm32")]
+        if *mouse_pressed != rotating {
+            self.lock_pointer(*mouse_pressed);
+        } else if *mouse_pressed && self.pointer_lock_lost() {
+            // The browser left the lock on its own, with Escape, the camera stops turning
+            // until the next press
+            *mouse_pressed = false;
+            self.lock_pointer(false);
+        }
+
+        let controller = &self.scene.camera_controller;
+        let rotates = controller.triggers(event, Action::Rotate);
+        if !consumed && controller.triggers(event, Action::FrameSelected) {
+            self.scene.frame_object(self.selected_object, self.aspect());
+        }
+        if !consumed && self.scene.camera_controller.triggers(event, Action::Help) {
+            self.show_help = !self.show_help;
+        }
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = glm::vec2(position.x as f32, position.y as f32);
+            }
+            // clicking on geometry focuses on it, the orbit also turns around it, unless
+            // the left button is bound to turning the camera
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if !consumed && !rotates => self.select_at_cursor(),
+            // one finger turns the camera, two pan and pinch to dolly in the current mode,
+            // a tap selects like a click
+            WindowEvent::Touch(touch) => {
+                let controller = &mut self.scene.camera_controller;
+                if let Some(position) = controller.handle_touch(touch, consumed) {
+                    self.cursor_position = position;
+                    self.select_at_cursor();
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } if !consumed => {
+                if let Some(index) = Bookmark::index_of_key(*key) {
+                    self.recall_bookmark(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn aspect(&self) -> f32 {
+        self.size.width as f32 / self.size.height as f32
+    }
+
+    /// Focuses on the geometry under the mouse and selects it, the orbit then turning
+    /// around it.
+    fn select_at_cursor(&mut self) {
+        if let Some(pick) = self.focus_at_cursor() {
+            if self.scene.camera_controller.mode() == ControllerMode::Orbit {
+                self.scene.camera_controller.pivot = pick.point;
+            }
+            self.selected_object = Some(pick.object);
+        }
+    }
+
+    /// Locks the pointer in the canvas while the camera turns, browsers only report the
+    /// motion of the mouse over the page otherwise.
+    #[cfg(target_arch = "wasm32")]
+    fn lock_pointer(&mut self, lock: bool) {
+        use winit::window::CursorGrabMode;
+
+        // The lock is granted later, it only counts once the browser holds it
+        self.pointer_locked = false;
+        let mode = if lock {
+            CursorGrabMode::Locked
+        } else {
+            CursorGrabMode::None
+        };
+        if let Err(error) = self.window.set_cursor_grab(mode) {
+            log::warn!("Pointer lock unavailable: {}", error);
+        }
+    }
+
+    /// Whether the browser released the pointer lock it held since the last call, as it
+    /// does on Escape without telling the page.
+    #[cfg(target_arch = "wasm32")]
+    fn pointer_lock_lost(&mut self) -> bool {
+        let locked = web_sys::window()
+            .and_then(|window| window.document())
+            .is_some_and(|document| document.pointer_lock_element().is_some());
+        let lost = self.pointer_locked && !locked;
+        self.pointer_locked = locked;
+        lost
+    }
+
+    /// Focuses on the geometry under the mouse, see [`Scene::focus_at`].
+    fn focus_at_cursor(&mut self) -> Option<Pick> {
+        let size = (self.size.width, self.size.height);
+        // The image may be smaller than the window, see `resize`
+        let window_size = self.window.inner_size();
+        // The image rows go from bottom to top
+        let uv = glm::vec2(
+            self.cursor_position.x / window_size.width as f32,
+            1.0 - self.cursor_position.y / window_size.height as f32,
+        );
+        self.scene.focus_at(size, uv)
+    }
+
+    /// Moves the camera smoothly to the bookmark `index`, if there is one.
+    fn recall_bookmark(&mut self, index: usize) {
+        if let Some(bookmark) = self.scene.bookmarks.get(index) {
+            self.camera_transition =
+                Some(CameraTransition::new(&self.scene.camera, &bookmark.camera));
+            self.current_bookmark = Some(index);
+            self.animation_playing = false;
+        }
     }
 
     pub fn device_event(&mut self, event: &DeviceEvent, mouse_pressed: bool) {
@@ -377,20 +575,92 @@ impl<'a> RenderContext<'a> {
     }
 
     pub fn update(&mut self, dt: std::time::Duration) {
+        self.frame_time = dt;
         self.scene
             .camera_controller
             .update_camera(&mut self.scene.camera, dt);
+        if self.animation_playing {
+            self.animation_time += dt.as_secs_f32();
+            if self.animation_time >= self.animation.end() {
+                self.animation_time = self.animation.end();
+                self.animation_playing = false;
+            }
+            self.scene.camera = self
+                .animation
+                .camera_at(self.animation_time, &self.scene.camera);
+        }
+        if let Some(transition) = &mut self.camera_transition {
+            if transition.update(&mut self.scene.camera, dt) {
+                self.camera_transition = None;
+                self.scene
+                    .camera_controller
+                    .center_pivot(&self.scene.camera);
+            }
+        }
 
         if self.latest_scene != self.scene {
-            let samples_per_pixel = self.latest_scene.render_param.samples_per_pixel;
+            self.path_tracer
+                .upload_scene(&self.device, &self.queue, &self.scene);
+
             self.latest_scene = self.scene.clone();
             self.scene.render_param.total_samples = 0;
-            self.scene.render_param.samples_per_pixel = samples_per_pixel;
-            self.latest_scene.render_param.samples_per_pixel = samples_per_pixel;
         }
     }
 
+    /// Saves the accumulation to `checkpoint_path`, blocking until it is read back.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_checkpoint(&mut self) -> std::io::Result<()> {
+        let checkpoint = self.path_tracer.checkpoint(
+            &self.device,
+            &self.queue,
+            &self.scene,
+            self.progress.elapsed(),
+        );
+        checkpoint.save(std::path::Path::new(&self.checkpoint_path))
+    }
+
+    /// Continues the accumulation saved in `checkpoint_path`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_checkpoint(&mut self) -> std::io::Result<()> {
+        let checkpoint = Checkpoint::load(
+            std::path::Path::new(&self.checkpoint_path),
+            (self.size.width, self.size.height),
+        )?;
+        self.path_tracer
+            .restore(&self.queue, &mut self.scene, &checkpoint)?;
+        self.progress.restore(checkpoint.elapsed);
+        Ok(())
+    }
+
+    /// Saves a checkpoint if asked to, to call before the app closes.
+    pub fn on_exit(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.save_on_exit && self.scene.render_param.total_samples > 0 {
+            match self.save_checkpoint() {
+                Ok(()) => log::info!("Checkpoint saved to {}", self.checkpoint_path),
+                Err(error) => log::error!("Failed to save the checkpoint: {}", error),
+            }
+        }
+    }
+
+    /// The convergence of the render since the last call, if any.
+    pub fn take_converged(&mut self) -> Option<Converged> {
+        self.converged.take()
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(request) = self.checkpoint_request.take() {
+            self.checkpoint_status = match request {
+                CheckpointRequest::Save => self.save_checkpoint().map(|()| "Saved"),
+                CheckpointRequest::Load => self.load_checkpoint().map(|()| "Loaded"),
+            }
+            .map_or_else(
+                |error| format!("{:?} failed: {}", request, error),
+                String::from,
+            );
+        }
+
         let output = self.surface.get_current_texture()?;
 
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor {
@@ -409,6 +679,64 @@ impl<'a> RenderContext<'a> {
                 label: Some("Render Encoder"),
             });
 
+        {
+            self.scene.frame_data.width = self.size.width;
+            self.scene.frame_data.height = self.size.height;
+            self.scene.frame_data.index += 1;
+
+            // The cost of a sample comes from the GPU timer when there is one, otherwise
+            // from the whole previous frame, presentation included
+            match &mut self.gpu_timer {
+                Some(timer) => {
+                    if let Some(elapsed) = timer.read(&self.device) {
+                        self.frame_budget.record(elapsed, self.timed_samples);
+                    }
+                }
+                None => self
+                    .frame_budget
+                    .record(self.frame_time, self.scene.render_param.samples_per_pixel),
+            }
+            if let Some(noisy_pixels) = self.path_tracer.read_noisy_pixels(&self.device) {
+                self.progress
+                    .record_noisy_pixels(noisy_pixels, self.counted_restarts);
+            }
+            if let Some(converged) = self
+                .progress
+                .update(&self.scene.render_param, self.frame_time)
+            {
+                self.converged = Some(converged);
+            }
+            if self.progress.is_running() {
+                self.frame_budget.set_limit(
+                    self.path_tracer
+                        .samples_per_frame_limit(&self.scene.render_param),
+                );
+                self.scene.render_param.update(&self.frame_budget);
+            } else {
+                self.scene.render_param.samples_per_pixel = 0;
+            }
+
+            self.path_tracer.update_uniforms(&self.queue, &self.scene);
+        }
+
+        if self.scene.render_param.samples_per_pixel > 0 {
+            encoder.insert_debug_marker("Path tracer Pass");
+            if self.path_tracer.counts_noisy_pixels() {
+                self.counted_restarts = self.progress.restarts();
+            }
+            match &mut self.gpu_timer {
+                Some(timer) => {
+                    let timestamp_writes = timer.compute_pass_writes();
+                    if timestamp_writes.is_some() {
+                        self.timed_samples = self.scene.render_param.samples_per_pixel;
+                    }
+                    self.path_tracer.dispatch(&mut encoder, timestamp_writes);
+                    timer.resolve(&mut encoder);
+                }
+                None => self.path_tracer.dispatch(&mut encoder, None),
+            }
+        }
+
         encoder.insert_debug_marker("Render Pass");
 
         {
@@ -433,39 +761,15 @@ impl<'a> RenderContext<'a> {
             });
 
             render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.image_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.scene_bind_group, &[]);
+            render_pass.set_bind_group(0, &self.display_bind_group, &[]);
             render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             render_pass.draw(0..VERTICES_LEN as u32, 0..1);
         }
 
         {
-            let camera = GpuCamera::new(&self.scene.camera, (self.size.width, self.size.height));
-
-            self.queue
-                .write_buffer(&self.camera_buffer.handle(), 0, bytemuck::bytes_of(&camera));
-
-            self.scene.frame_data.width = self.size.width;
-            self.scene.frame_data.height = self.size.height;
-            self.scene.frame_data.index += 1;
-
-            self.queue.write_buffer(
-                &self.frame_data_buffer.handle(),
-                0,
-                bytemuck::bytes_of(&self.scene.frame_data),
-            );
-
-            self.scene.render_param.update();
-
-            self.queue.write_buffer(
-                &self.render_param_buffer.handle(),
-                0,
-                bytemuck::bytes_of(&self.scene.render_param),
-            );
-        }
-
-        {
-            self.egui_renderer.begin_frame(&self.window);
+            self.egui_renderer.begin_frame(self.window);
+            let aspect = self.aspect();
+            let mut recall = None;
 
             egui::Window::new("Params")
                 // .resizable(true)
@@ -479,6 +783,139 @@ impl<'a> RenderContext<'a> {
                     //     println!("boom!")
                     // }
 
+                    ui.horizontal(|ui| {
+                        let state = self.progress.state();
+                        match self.progress.stop_reason() {
+                            Some(reason) => ui.label(format!("{:?} ({:?})", state, reason)),
+                            None => ui.label(format!("{:?}", state)),
+                        };
+                        if state == RenderState::Running {
+                            if ui.button("Pause").clicked() {
+                                self.progress.pause();
+                            }
+                        } else if ui.button("Resume").clicked() {
+                            self.progress.resume();
+                        }
+                        if ui.button("Restart").clicked() {
+                            self.progress.restart(&mut self.scene.render_param);
+                        }
+                    });
+
+                    // the render also stops at the max samples per pixel
+                    ui.horizontal(|ui| {
+                        let criteria = &mut self.progress.criteria;
+                        let mut limit_time = criteria.max_time.is_some();
+                        ui.checkbox(&mut limit_time, "Stop after");
+                        let mut seconds = criteria.max_time.map_or(60, |time| time.as_secs());
+                        ui.add_enabled(
+                            limit_time,
+                            egui::Slider::new(&mut seconds, 1..=3600)
+                                .logarithmic(true)
+                                .text("s"),
+                        );
+                        criteria.max_time =
+                            limit_time.then(|| std::time::Duration::from_secs(seconds));
+                    });
+                    ui.checkbox(
+                        &mut self.progress.criteria.noise,
+                        "Stop once every pixel is below the noise threshold",
+                    );
+
+                    // checkpoints only resume on the same scene, view and window size
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        ui.horizontal(|ui| {
+                            ui.label("Checkpoint:");
+                            ui.text_edit_singleline(&mut self.checkpoint_path);
+                            if ui.button("Save").clicked() {
+                                self.checkpoint_request = Some(CheckpointRequest::Save);
+                            }
+                            if ui.button("Load").clicked() {
+                                self.checkpoint_request = Some(CheckpointRequest::Load);
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.save_on_exit, "Save on exit");
+                            ui.label(&self.checkpoint_status);
+                        });
+                    }
+
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        ui.label("Tracer:");
+                        let mut mode = self.path_tracer.mode();
+                        egui::ComboBox::from_id_salt("tracer mode")
+                            .selected_text(format!("{:?}", mode))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(
+                                    &mut mode,
+                                    TracerMode::Megakernel,
+                                    "Megakernel",
+                                );
+                                ui.selectable_value(&mut mode, TracerMode::Wavefront, "Wavefront");
+                            });
+                        if mode != self.path_tracer.mode() {
+                            self.path_tracer.set_mode(mode);
+                            self.scene.render_param.total_samples = 0;
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Sampler:");
+                        let mut sampler = self.scene.render_param.sampler();
+                        egui::ComboBox::from_id_salt("sampler")
+                            .selected_text(format!("{:?}", sampler))
+                            .show_ui(ui, |ui| {
+                                for kind in SamplerKind::ALL {
+                                    ui.selectable_value(&mut sampler, kind, format!("{:?}", kind));
+                                }
+                            });
+                        if sampler != self.scene.render_param.sampler() {
+                            self.scene.render_param.sampler_kind = sampler as u32;
+                            self.scene.render_param.total_samples = 0;
+                        }
+                    });
+
+                    // the same seed and settings always give the same image
+                    ui.horizontal(|ui| {
+                        ui.label("Seed:");
+                        if ui
+                            .add(egui::DragValue::new(&mut self.scene.render_param.seed))
+                            .changed()
+                        {
+                            self.scene.render_param.total_samples = 0;
+                        }
+                    });
+
+                    // the radius is reset to the usual one of the new filter
+                    ui.horizontal(|ui| {
+                        ui.label("Pixel filter:");
+                        let mut filter = self.scene.render_param.filter();
+                        egui::ComboBox::from_id_salt("pixel filter")
+                            .selected_text(format!("{:?}", filter))
+                            .show_ui(ui, |ui| {
+                                for kind in PixelFilter::ALL {
+                                    ui.selectable_value(&mut filter, kind, format!("{:?}", kind));
+                                }
+                            });
+                        if filter != self.scene.render_param.filter() {
+                            self.scene.render_param.filter_kind = filter as u32;
+                            self.scene.render_param.filter_radius = filter.default_radius();
+                            self.scene.render_param.total_samples = 0;
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Filter radius:");
+                        let radius = &mut self.scene.render_param.filter_radius;
+                        if ui
+                            .add(egui::Slider::new(radius, 0.5..=4.0).text("pixels"))
+                            .changed()
+                        {
+                            self.scene.render_param.total_samples = 0;
+                        }
+                    });
+
                     // slider for changing the max samples per pixel
                     ui.horizontal(|ui| {
                         ui.label("Max samples per pixel:");
@@ -491,6 +928,22 @@ impl<'a> RenderContext<'a> {
                         );
                     });
 
+                    // samples per frame follow the measured cost of a sample
+                    ui.horizontal(|ui| {
+                        ui.label("Frame budget:");
+                        let mode = &mut self.frame_budget.mode;
+                        ui.selectable_value(mode, FrameBudgetMode::Interactive, "Interactive");
+                        ui.selectable_value(mode, FrameBudgetMode::Final, "Final");
+                    });
+                    if self.frame_budget.mode == FrameBudgetMode::Interactive {
+                        ui.horizontal(|ui| {
+                            ui.label("Target frame time:");
+                            let mut target = self.frame_budget.target.as_millis() as u64;
+                            ui.add(egui::Slider::new(&mut target, 4..=100).text("ms"));
+                            self.frame_budget.target = std::time::Duration::from_millis(target);
+                        });
+                    }
+
                     // slider for changing the max depth of the ray
                     ui.horizontal(|ui| {
                         ui.label("Max depth:");
@@ -500,29 +953,383 @@ impl<'a> RenderContext<'a> {
                         );
                     });
 
-                    ui.separator();
-
+                    // paths are only terminated by russian roulette after this depth
                     ui.horizontal(|ui| {
-                        ui.label("Field of view:");
+                        ui.label("Russian roulette depth:");
                         ui.add(
-                            egui::Slider::new(&mut self.scene.camera.vfov, 2.0..=179.0).text("fov"),
+                            egui::Slider::new(&mut self.scene.render_param.rr_min_depth, 1..=100)
+                                .text("min depth"),
                         );
                     });
 
+                    // pixels below the noise threshold stop receiving samples
+                    let render_param = &mut self.scene.render_param;
+                    let mut adaptive = render_param.adaptive != 0;
+                    ui.checkbox(&mut adaptive, "Adaptive sampling");
+                    render_param.adaptive = adaptive as u32;
+                    ui.add_enabled_ui(adaptive || self.progress.criteria.noise, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Noise threshold:");
+                            ui.add(
+                                egui::Slider::new(&mut render_param.noise_threshold, 0.001..=0.2)
+                                    .logarithmic(true)
+                                    .text("relative error"),
+                            );
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Min samples:");
+                            ui.add(
+                                egui::Slider::new(&mut render_param.adaptive_min_samples, 1..=256)
+                                    .text("samples"),
+                            );
+                        });
+                    });
+                    let mut show_heatmap = render_param.show_heatmap != 0;
+                    ui.checkbox(&mut show_heatmap, "Show sample count heatmap");
+                    render_param.show_heatmap = show_heatmap as u32;
+
+                    // firefly suppression, darkens the highlights to remove the noise
+                    let mut suppression_changed = false;
+                    for (label, unit, value, default, range) in [
+                        (
+                            "Clamp direct",
+                            "luminance",
+                            &mut render_param.clamp_direct,
+                            RenderParam::DEFAULT_CLAMP_DIRECT,
+                            1.0..=1000.0,
+                        ),
+                        (
+                            "Clamp indirect",
+                            "luminance",
+                            &mut render_param.clamp_indirect,
+                            RenderParam::DEFAULT_CLAMP_INDIRECT,
+                            0.1..=1000.0,
+                        ),
+                        (
+                            "Reject outliers",
+                            "sigma",
+                            &mut render_param.outlier_sigma,
+                            RenderParam::DEFAULT_OUTLIER_SIGMA,
+                            1.0..=10.0,
+                        ),
+                    ] {
+                        ui.horizontal(|ui| {
+                            let mut enabled = *value > 0.0;
+                            if ui.checkbox(&mut enabled, label).changed() {
+                                *value = if enabled { default } else { 0.0 };
+                                suppression_changed = true;
+                            }
+                            if enabled {
+                                suppression_changed |= ui
+                                    .add(
+                                        egui::Slider::new(value, range)
+                                            .logarithmic(true)
+                                            .text(unit),
+                                    )
+                                    .changed();
+                            }
+                        });
+                    }
+                    if suppression_changed {
+                        render_param.total_samples = 0;
+                    }
+
+                    ui.separator();
+
+                    // in orbit mode a click on the geometry sets the pivot
+                    ui.horizontal(|ui| {
+                        ui.label("Camera:");
+                        let mut mode = self.scene.camera_controller.mode();
+                        ui.selectable_value(&mut mode, ControllerMode::Fly, "Fly");
+                        ui.selectable_value(&mut mode, ControllerMode::Orbit, "Orbit");
+                        self.scene
+                            .camera_controller
+                            .set_mode(mode, &self.scene.camera);
+                        let input_map = &self.scene.camera_controller.input_map;
+                        let frame = input_map.describe(Action::FrameSelected);
+                        let help = input_map.describe(Action::Help);
+                        if ui.button(format!("Frame selected ({})", frame)).clicked() {
+                            self.scene.frame_object(self.selected_object, aspect);
+                        }
+                        if ui.button(format!("Controls ({})", help)).clicked() {
+                            self.show_help = true;
+                        }
+                    });
+
+                    // the number keys recall the first nine bookmarks
+                    ui.horizontal(|ui| {
+                        ui.label("Bookmark:");
+                        let bookmarks = &mut self.scene.bookmarks;
+                        let current = self.current_bookmark.and_then(|i| bookmarks.get(i));
+                        egui::ComboBox::from_id_salt("bookmark")
+                            .selected_text(current.map_or("", |bookmark| &bookmark.name))
+                            .show_ui(ui, |ui| {
+                                for (i, bookmark) in bookmarks.iter().enumerate() {
+                                    let label = match i {
+                                        0..=8 => format!("{} ({})", bookmark.name, i + 1),
+                                        _ => bookmark.name.clone(),
+                                    };
+                                    let selected = self.current_bookmark == Some(i);
+                                    if ui.selectable_label(selected, label).clicked() {
+                                        recall = Some(i);
+                                    }
+                                }
+                            });
+                        if let Some(i) = self.current_bookmark {
+                            if ui.button("Update").clicked() {
+                                bookmarks[i].camera = self.scene.camera;
+                            }
+                            if ui.button("Remove").clicked() {
+                                bookmarks.remove(i);
+                                self.current_bookmark = None;
+                            }
+                        }
+                    });
                     ui.horizontal(|ui| {
-                        ui.label("Aperture:");
                         ui.add(
-                            egui::Slider::new(&mut self.scene.camera.aperture, 0.0..=1.0)
-                                .text("aperture"),
+                            egui::TextEdit::singleline(&mut self.bookmark_name).hint_text("name"),
                         );
+                        if ui.button("Add bookmark").clicked() {
+                            let bookmarks = &mut self.scene.bookmarks;
+                            let name = match self.bookmark_name.trim() {
+                                "" => format!("View {}", bookmarks.len() + 1),
+                                name => name.to_string(),
+                            };
+                            bookmarks.push(Bookmark::new(name, self.scene.camera));
+                            self.current_bookmark = Some(bookmarks.len() - 1);
+                            self.bookmark_name.clear();
+                        }
+                    });
+
+                    // panoramas ignore the field of view, orthographic views the position
+                    ui.horizontal(|ui| {
+                        ui.label("Projection:");
+                        let projection = &mut self.scene.camera.projection;
+                        egui::ComboBox::from_id_salt("projection")
+                            .selected_text(projection.name())
+                            .show_ui(ui, |ui| {
+                                for kind in Projection::ALL {
+                                    let selected = projection.id() == kind.id();
+                                    if ui.selectable_label(selected, kind.name()).clicked()
+                                        && !selected
+                                    {
+                                        *projection = kind;
+                                    }
+                                }
+                            });
+                        match projection {
+                            Projection::Orthographic { height } => {
+                                ui.add(
+                                    egui::Slider::new(height, 0.01..=100.0)
+                                        .logarithmic(true)
+                                        .text("height"),
+                                );
+                            }
+                            Projection::Fisheye { fov } => {
+                                ui.add(egui::Slider::new(fov, 10.0..=360.0).text("fov"));
+                            }
+                            Projection::StereoEquirectangular { ipd } => {
+                                ui.add(egui::Slider::new(ipd, 0.0..=1.0).text("ipd"));
+                            }
+                            Projection::Perspective | Projection::Equirectangular => {}
+                        }
                     });
 
+                    // a physical camera derives the field of view and aperture
+                    let camera = &mut self.scene.camera;
+                    let mut physical = camera.physical.is_some();
+                    ui.checkbox(&mut physical, "Physical camera");
+                    if physical != camera.physical.is_some() {
+                        camera.physical =
+                            physical.then(|| PhysicalCamera::with_vfov(camera.vfov, aspect));
+                    }
+                    if let Some(physical) = &mut camera.physical {
+                        physical_camera_ui(ui, physical);
+                        ui.label(format!(
+                            "Field of view: {:.1}°, EV100: {:.1}",
+                            physical.vfov(aspect),
+                            physical.ev100()
+                        ));
+                    } else {
+                        ui.horizontal(|ui| {
+                            ui.label("Field of view:");
+                            ui.add(egui::Slider::new(&mut camera.vfov, 2.0..=179.0).text("fov"));
+                        });
+
+                        ui.horizontal(|ui| {
+                            ui.label("Aperture:");
+                            ui.add(
+                                egui::Slider::new(&mut camera.aperture, 0.0..=1.0).text("aperture"),
+                            );
+                        });
+                    }
+
+                    // clicking the image focuses on a point, moving the slider lets go of it
                     ui.horizontal(|ui| {
+                        let camera = &mut self.scene.camera;
                         ui.label("Focus distance:");
-                        ui.add(
-                            egui::Slider::new(&mut self.scene.camera.focus_distance, 0.0..=100.0)
+                        let slider = ui.add(
+                            egui::Slider::new(&mut camera.focus_distance, 0.0..=100.0)
                                 .text("focus distance"),
                         );
+                        if slider.changed() {
+                            camera.focus_point = None;
+                        }
+                        if camera.focus_point.is_some() && ui.button("Release focus").clicked() {
+                            camera.focus_point = None;
+                        }
+                    });
+
+                    // fewer than 3 blades leave the aperture circular
+                    ui.horizontal(|ui| {
+                        let shape = &mut self.scene.camera.aperture_shape;
+                        ui.label("Aperture shape:");
+                        ui.add(
+                            egui::Slider::new(&mut shape.blades, 0..=Aperture::MAX_BLADES)
+                                .text("blades"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut shape.rotation, 0.0..=360.0).text("rotation"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut shape.squeeze, 0.25..=4.0)
+                                .logarithmic(true)
+                                .text("anamorphic"),
+                        );
+                    });
+                    if let Some(mask) = &self.scene.aperture_mask {
+                        let (width, height) = mask.dimensions();
+                        let mut clear = false;
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Aperture mask: {}x{}", width, height));
+                            clear = ui.button("Clear").clicked();
+                        });
+                        if clear {
+                            self.scene.aperture_mask = None;
+                        }
+                    }
+
+                    // the scene moves from time 0 to 1 over the frame, rays are traced
+                    // while the shutter is open
+                    ui.horizontal(|ui| {
+                        let shutter = &mut self.scene.camera.shutter;
+                        ui.label("Shutter:");
+                        ui.add(egui::Slider::new(&mut shutter.open, 0.0..=1.0).text("open"));
+                        ui.add(egui::Slider::new(&mut shutter.close, 0.0..=1.0).text("close"));
+                    });
+                    ui.horizontal(|ui| {
+                        let translation = &mut self.scene.camera.motion.translation;
+                        ui.label("Camera motion:");
+                        for (value, axis) in translation.iter_mut().zip(["x", "y", "z"]) {
+                            ui.add(egui::DragValue::new(value).speed(0.01).prefix(axis));
+                        }
+                    });
+
+                    // the headless mode renders the keyframes with --camera-path
+                    ui.collapsing("Animation", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Keyframe at:");
+                            ui.add(
+                                egui::DragValue::new(&mut self.keyframe_time)
+                                    .speed(0.1)
+                                    .range(0.0..=3600.0)
+                                    .suffix(" s"),
+                            );
+                            if ui.button("Add keyframe").clicked() {
+                                self.animation
+                                    .insert(Keyframe::new(self.keyframe_time, &self.scene.camera));
+                                self.animation_time = self.keyframe_time;
+                                self.keyframe_time += 1.0;
+                            }
+                        });
+
+                        let mut go_to = None;
+                        let mut remove = None;
+                        for (i, keyframe) in self.animation.keyframes().iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{:.2} s", keyframe.time));
+                                if ui.button("Go to").clicked() {
+                                    go_to = Some(keyframe.time);
+                                }
+                                if ui.button("Remove").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = remove {
+                            self.animation.remove(i);
+                        }
+
+                        if !self.animation.is_empty() {
+                            ui.horizontal(|ui| {
+                                let (start, end) = (self.animation.start(), self.animation.end());
+                                let label = if self.animation_playing {
+                                    "Stop"
+                                } else {
+                                    "Play"
+                                };
+                                if ui.button(label).clicked() {
+                                    self.animation_playing = !self.animation_playing;
+                                    if self.animation_time >= end {
+                                        self.animation_time = start;
+                                    }
+                                }
+                                let slider = ui.add(
+                                    egui::Slider::new(&mut self.animation_time, start..=end)
+                                        .text("s"),
+                                );
+                                if slider.changed() {
+                                    go_to = Some(self.animation_time);
+                                }
+                                if ui.button("Clear").clicked() {
+                                    self.animation.clear();
+                                    self.animation_playing = false;
+                                }
+                            });
+                        }
+                        if let Some(time) = go_to {
+                            self.animation_time = time;
+                            self.animation_playing = false;
+                            self.scene.camera = self.animation.camera_at(time, &self.scene.camera);
+                        }
+
+                        #[cfg(not(target_arch = "wasm32"))]
+                        {
+                            ui.horizontal(|ui| {
+                                ui.label("Keyframes:");
+                                ui.text_edit_singleline(&mut self.animation_path);
+                                let path = std::path::Path::new(&self.animation_path);
+                                if ui.button("Save").clicked() {
+                                    self.animation_status = match self.animation.save(path) {
+                                        Ok(()) => String::from("Saved"),
+                                        Err(error) => error.to_string(),
+                                    };
+                                }
+                                if ui.button("Load").clicked() {
+                                    self.animation_status = match CameraAnimation::load(path) {
+                                        Ok(animation) => {
+                                            self.animation = animation;
+                                            self.animation_time = self.animation.start();
+                                            self.keyframe_time = self.animation.end() + 1.0;
+                                            String::from("Loaded")
+                                        }
+                                        Err(error) => error.to_string(),
+                                    };
+                                }
+                            });
+                            ui.label(&self.animation_status);
+                        }
+                    });
+
+                    ui.separator();
+
+                    ui.collapsing("Materials", |ui| {
+                        for (i, material) in self.scene.materials.iter_mut().enumerate() {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("#{}", i));
+                                material_ui(ui, material);
+                            });
+                        }
                     });
 
                     ui.separator();
@@ -537,6 +1344,28 @@ impl<'a> RenderContext<'a> {
                             self.scene.render_param.samples_max_per_pixel
                         ));
                         ui.label(format!("FPS: {:.2}", self.fps));
+                        ui.label(format!(
+                            "Render time: {:.1} s",
+                            self.progress.elapsed().as_secs_f64()
+                        ));
+                        if let Some(noisy_pixels) = self.progress.noisy_pixels() {
+                            ui.label(format!("Noisy pixels: {}", noisy_pixels));
+                        }
+                        ui.label(format!(
+                            "Samples per frame: {}",
+                            self.scene.render_param.samples_per_pixel
+                        ));
+                        if let Some(sample_time) = self.frame_budget.sample_time() {
+                            ui.label(format!(
+                                "Time per sample: {:.2} ms ({})",
+                                sample_time.as_secs_f64() * 1000.0,
+                                if self.gpu_timer.is_some() {
+                                    "GPU timer"
+                                } else {
+                                    "frame time"
+                                }
+                            ));
+                        }
                     });
                     ui.separator();
 
@@ -546,12 +1375,95 @@ impl<'a> RenderContext<'a> {
                     ui.label(format!("Eye position: {:?}", self.scene.camera.eye_pos));
                     ui.label(format!("Up vector: {:?}", self.scene.camera.up));
                 });
+            if let Some(i) = recall {
+                self.recall_bookmark(i);
+            }
+
+            let mut show_help = self.show_help;
+            egui::Window::new("Controls")
+                .open(&mut show_help)
+                .collapsible(false)
+                .show(self.egui_renderer.context(), |ui| {
+                    let input_map = &mut self.scene.camera_controller.input_map;
+                    egui::Grid::new("controls").striped(true).show(ui, |ui| {
+                        for action in Action::ALL {
+                            ui.label(action.description());
+                            ui.label(input_map.describe(action));
+                            ui.end_row();
+                        }
+                        // not rebindable
+                        for (description, input) in [
+                            (
+                                "Focus on the geometry, pivot around it in orbit mode",
+                                "MouseLeft, tap",
+                            ),
+                            ("Move the focus, dolly in orbit mode", "Scroll"),
+                            ("Turn the camera", "One finger drag"),
+                            ("Pan in orbit mode, pinch like scrolling", "Two finger drag"),
+                            ("Recall a bookmark", "Digit1 to Digit9"),
+                            #[cfg(not(target_arch = "wasm32"))]
+                            ("Quit", "Escape"),
+                        ] {
+                            ui.label(description);
+                            ui.label(input);
+                            ui.end_row();
+                        }
+                    });
+
+                    ui.checkbox(&mut input_map.invert_y, "Invert Y");
+                    ui.horizontal(|ui| {
+                        ui.label("Fast:");
+                        ui.add(
+                            egui::Slider::new(&mut input_map.fast_multiplier, 1.0..=20.0)
+                                .logarithmic(true)
+                                .text("x speed"),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Slow:");
+                        ui.add(
+                            egui::Slider::new(&mut input_map.slow_multiplier, 0.01..=1.0)
+                                .logarithmic(true)
+                                .text("x speed"),
+                        );
+                    });
+                    if ui.button("Reset to defaults").clicked() {
+                        *input_map = InputMap::default();
+                    }
+
+                    // bindings are edited in the config file, loaded again here
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        ui.horizontal(|ui| {
+                            ui.label("Config:");
+                            ui.text_edit_singleline(&mut self.controls_path);
+                            let path = std::path::Path::new(&self.controls_path);
+                            if ui.button("Save").clicked() {
+                                self.controls_status = match input_map.save(path) {
+                                    Ok(()) => String::from("Saved"),
+                                    Err(error) => error.to_string(),
+                                };
+                            }
+                            if ui.button("Load").clicked() {
+                                self.controls_status = match InputMap::load(path) {
+                                    Ok(loaded) => {
+                                        *input_map = loaded;
+                                        String::from("Loaded")
+                                    }
+                                    Err(error) => error.to_string(),
+                                };
+                            }
+                        });
+                        ui.label(&self.controls_status);
+                    }
+                });
+            self.show_help = show_help;
 
             self.egui_renderer.end_frame_and_draw(
                 &self.device,
                 &self.queue,
                 &mut encoder,
-                &self.window,
+                self.window,
                 &view,
                 ScreenDescriptor {
                     size_in_pixels: self.size.into(),
@@ -562,8 +1474,104 @@ impl<'a> RenderContext<'a> {
 
         // submit will accept anything that implements IntoIter
         self.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.after_submit();
+        }
+        self.path_tracer.after_submit();
         output.present();
 
         Ok(())
     }
 }
+
+fn texture_ui(ui: &mut egui::Ui, texture: &mut Texture, max: f32) {
+    // Only single color textures are editable for now
+    if let [color] = texture.as_mut_slice() {
+        for c in color.iter_mut() {
+            ui.add(egui::DragValue::new(c).speed(0.01).range(0.0..=max));
+        }
+    }
+}
+
+fn material_ui(ui: &mut egui::Ui, material: &mut Material) {
+    match material {
+        Material::Lambertian { albedo } => {
+            ui.label("Lambertian");
+            texture_ui(ui, albedo, 1.0);
+        }
+        Material::Metal { albedo, fuzz } => {
+            ui.label("Metal");
+            texture_ui(ui, albedo, 1.0);
+            ui.add(egui::Slider::new(fuzz, 0.0..=1.0).text("fuzz"));
+        }
+        Material::Dialectric { ref_idx } => {
+            ui.label("Dielectric");
+            ui.add(egui::Slider::new(ref_idx, 1.0..=3.0).text("ior"));
+        }
+        Material::DiffuseLight { emit } => {
+            ui.label("Light");
+            texture_ui(ui, emit, 100.0);
+        }
+    }
+}
+
+fn physical_camera_ui(ui: &mut egui::Ui, physical: &mut PhysicalCamera) {
+    ui.horizontal(|ui| {
+        ui.label("Focal length:");
+        ui.add(
+            egui::Slider::new(&mut physical.focal_length, 8.0..=400.0)
+                .logarithmic(true)
+                .text("mm"),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Aperture:");
+        ui.add(
+            egui::Slider::new(&mut physical.f_number, 0.7..=32.0)
+                .logarithmic(true)
+                .text("f-number"),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Sensor width:");
+        ui.add(egui::Slider::new(&mut physical.sensor_width, 4.0..=70.0).text("mm"));
+        if ui.button("Full frame").clicked() {
+            physical.sensor_width = PhysicalCamera::FULL_FRAME_WIDTH;
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("ISO:");
+        ui.add(egui::Slider::new(&mut physical.iso, 50.0..=25600.0).logarithmic(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Shutter:");
+        ui.add(
+            egui::Slider::new(&mut physical.shutter, 1.0 / 8000.0..=30.0)
+                .logarithmic(true)
+                .text("s"),
+        );
+    });
+    ui.horizontal(|ui| {
+        ui.label("Exposure compensation:");
+        ui.add(egui::Slider::new(&mut physical.exposure_compensation, -10.0..=20.0).text("EV"));
+    });
+}
+
+/// `size` scaled down to fit the largest texture of `device` and the storage buffers
+/// holding a value per pixel, see [`PathTracer::max_pixels`]. A canvas on a high density
+/// screen can outgrow them, the image is then stretched over the canvas.
+fn fit_texture_size(
+    size: winit::dpi::PhysicalSize<u32>,
+    device: &wgpu::Device,
+) -> winit::dpi::PhysicalSize<u32> {
+    let max_side = device.limits().max_texture_dimension_2d as f64;
+    let max_pixels = PathTracer::max_pixels(device) as f64;
+    let (width, height) = (size.width.max(1) as f64, size.height.max(1) as f64);
+    let scale = (max_side / width.max(height))
+        .min((max_pixels / (width * height)).sqrt())
+        .min(1.0);
+    winit::dpi::PhysicalSize::new(
+        ((width * scale) as u32).max(1),
+        ((height * scale) as u32).max(1),
+    )
+}
@@ -0,0 +1,251 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{self, BufRead, Write};
+
+use super::Camera;
+
+/// Camera settings at `time` along a [`CameraAnimation`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    /// In seconds from the start of the animation.
+    pub time: f32,
+    pub eye_pos: glm::Vec3,
+    pub eye_dir: glm::Vec3,
+    pub vfov: f32,
+    pub focus_distance: f32,
+    pub aperture: f32,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, camera: &Camera) -> Self {
+        Self {
+            time,
+            eye_pos: camera.eye_pos,
+            eye_dir: glm::normalize(&camera.eye_dir),
+            vfov: camera.vfov,
+            focus_distance: camera.focus_distance,
+            aperture: camera.aperture,
+        }
+    }
+}
+
+/// Camera path going through keyframes sorted by time. In between, the keyframes are
+/// interpolated by Catmull-Rom splines, the tangents following the neighbouring
+/// keyframes so that the camera keeps its speed through them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraAnimation {
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraAnimation {
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Adds `keyframe` in time order, replacing the one at the same time.
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        match self
+            .keyframes
+            .binary_search_by(|other| other.time.total_cmp(&keyframe.time))
+        {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Keyframe {
+        self.keyframes.remove(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    /// Time of the first keyframe, zero without keyframes.
+    pub fn start(&self) -> f32 {
+        self.keyframes.first().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Time of the last keyframe, zero without keyframes.
+    pub fn end(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Number of frames at `fps` from the first keyframe to the last one included.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn frame_count(&self, fps: f32) -> u32 {
+        if self.is_empty() {
+            return 0;
+        }
+        // Rounded so that a last keyframe on a frame is not lost to rounding errors
+        ((self.end() - self.start()) * fps + 1e-3).floor() as u32 + 1
+    }
+
+    /// `camera` moved to `time` along the path, staying on the first or last keyframe
+    /// outside of it. The point in focus is released, the keyframes give the focus.
+    pub fn camera_at(&self, time: f32, camera: &Camera) -> Camera {
+        let Some(last) = self.keyframes.len().checked_sub(1) else {
+            return *camera;
+        };
+        let time = time.clamp(self.start(), self.end());
+        let index = self
+            .keyframes
+            .partition_point(|keyframe| keyframe.time <= time)
+            .clamp(1, last.max(1))
+            - 1;
+        let k0 = &self.keyframes[index.saturating_sub(1)];
+        let k1 = &self.keyframes[index];
+        let k2 = &self.keyframes[(index + 1).min(last)];
+        let k3 = &self.keyframes[(index + 2).min(last)];
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 {
+            (time - k1.time) / span
+        } else {
+            0.0
+        };
+
+        let mut camera = *camera;
+        camera.eye_pos = catmull_rom(
+            [k0.eye_pos, k1.eye_pos, k2.eye_pos, k3.eye_pos],
+            [k0.time, k1.time, k2.time, k3.time],
+            t,
+        );
+        let eye_dir = catmull_rom(
+            [k0.eye_dir, k1.eye_dir, k2.eye_dir, k3.eye_dir],
+            [k0.time, k1.time, k2.time, k3.time],
+            t,
+        );
+        // Opposite directions on both sides of a key cancel out, keep the last one then
+        if eye_dir.magnitude() > 1e-6 {
+            camera.eye_dir = glm::normalize(&eye_dir);
+        }
+        let scalar = |value: fn(&Keyframe) -> f32| {
+            catmull_rom(
+                [value(k0), value(k1), value(k2), value(k3)],
+                [k0.time, k1.time, k2.time, k3.time],
+                t,
+            )
+        };
+        camera.vfov = scalar(|keyframe| keyframe.vfov).clamp(2.0, 179.0);
+        camera.focus_distance =
+            scalar(|keyframe| keyframe.focus_distance).max(Camera::MIN_FOCUS_DISTANCE);
+        camera.aperture = scalar(|keyframe| keyframe.aperture).clamp(0.0, 1.0);
+        camera.focus_point = None;
+        camera
+    }
+
+    /// Writes one keyframe per line: time, position, direction, field of view, focus
+    /// distance and aperture, separated by spaces.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "# time x y z dir_x dir_y dir_z vfov focus_distance aperture"
+        )?;
+        for keyframe in &self.keyframes {
+            let [x, y, z] = keyframe.eye_pos.into();
+            let [dir_x, dir_y, dir_z] = keyframe.eye_dir.into();
+            writeln!(
+                writer,
+                "{} {} {} {} {} {} {} {} {} {}",
+                keyframe.time,
+                x,
+                y,
+                z,
+                dir_x,
+                dir_y,
+                dir_z,
+                keyframe.vfov,
+                keyframe.focus_distance,
+                keyframe.aperture
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reads the keyframes written by [`CameraAnimation::write`], in any order, skipping
+    /// empty lines and the ones starting with `#`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut animation = Self::default();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid keyframe on line {}", number + 1),
+                )
+            };
+            let values = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<f32>, _>>()
+                .map_err(|_| invalid())?;
+            let [time, x, y, z, dir_x, dir_y, dir_z, vfov, focus_distance, aperture] = values[..]
+            else {
+                return Err(invalid());
+            };
+            let eye_dir = glm::vec3(dir_x, dir_y, dir_z);
+            if eye_dir.magnitude() == 0.0 {
+                return Err(invalid());
+            }
+            animation.insert(Keyframe {
+                time,
+                eye_pos: glm::vec3(x, y, z),
+                eye_dir: glm::normalize(&eye_dir),
+                vfov,
+                focus_distance,
+                aperture,
+            });
+        }
+        Ok(animation)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &std::path::Path) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> io::Result<Self> {
+        Self::read(io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+/// Cubic Hermite curve from `p[1]` at `t` 0 to `p[2]` at `t` 1, its tangents taken
+/// from the neighbours of the ends at the given `times`, which may be spaced unevenly.
+fn catmull_rom<T>(p: [T; 4], times: [f32; 4], t: f32) -> T
+where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<f32, Output = T>,
+{
+    let span = times[2] - times[1];
+    let tangent = |before: usize, after: usize| {
+        let dt = times[after] - times[before];
+        if dt > 0.0 {
+            (p[after] - p[before]) * (span / dt)
+        } else {
+            p[after] * 0.0
+        }
+    };
+    let m1 = tangent(0, 2);
+    let m2 = tangent(1, 3);
+
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p[1] * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + m1 * (t3 - 2.0 * t2 + t)
+        + p[2] * (-2.0 * t3 + 3.0 * t2)
+        + m2 * (t3 - t2)
+}
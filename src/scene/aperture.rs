@@ -0,0 +1,80 @@
+use super::Scene;
+
+/// Shape of the lens opening, which out of focus highlights take.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aperture {
+    /// Number of straight blades closing the opening, below 3 it is circular.
+    pub blades: u32,
+    /// Rotation of the blades or of the mask, in degrees.
+    pub rotation: f32,
+    /// Anamorphic squeeze, the opening being `squeeze` times taller than wide.
+    pub squeeze: f32,
+}
+
+impl Default for Aperture {
+    fn default() -> Self {
+        Self {
+            blades: 0,
+            rotation: 0.0,
+            squeeze: 1.0,
+        }
+    }
+}
+
+impl Aperture {
+    pub const MAX_BLADES: u32 = 16;
+}
+
+impl Scene {
+    /// Sampling tables of `aperture_mask` read by `sample_aperture_mask` in aperture.wgsl:
+    /// its width and height, the cumulative luminance of its rows from the bottom, then
+    /// the cumulative luminance along each row. Only the size, zero, without a mask.
+    pub fn gpu_aperture_mask(&self) -> Vec<f32> {
+        let Some(mask) = &self.aperture_mask else {
+            return vec![0.0, 0.0];
+        };
+        let (width, height) = mask.dimensions();
+        // Textures are stored from the top row down, the lens is sampled from the bottom up
+        let luminance = |x: u32, y: u32| {
+            let [r, g, b] = mask.as_slice()[((height - 1 - y) * width + x) as usize];
+            (0.2126 * r + 0.7152 * g + 0.0722 * b).max(0.0)
+        };
+
+        let mut rows = Vec::with_capacity(height as usize + 1);
+        let mut columns = Vec::with_capacity((height * (width + 1)) as usize);
+        rows.push(0.0);
+        for y in 0..height {
+            let start = columns.len();
+            columns.push(0.0);
+            for x in 0..width {
+                columns.push(columns[columns.len() - 1] + luminance(x, y));
+            }
+            let row_sum = columns[columns.len() - 1];
+            normalize_cdf(&mut columns[start..]);
+            rows.push(rows[rows.len() - 1] + row_sum);
+        }
+        if rows[rows.len() - 1] <= 0.0 {
+            log::warn!("Ignoring the aperture mask, it is black");
+            return vec![0.0, 0.0];
+        }
+        normalize_cdf(&mut rows);
+
+        let mut data = vec![width as f32, height as f32];
+        data.extend(rows);
+        data.extend(columns);
+        data
+    }
+}
+
+/// Scales the cumulative sums `cdf` to end at 1, evenly spread when they are all zero.
+fn normalize_cdf(cdf: &mut [f32]) {
+    let n = cdf.len() - 1;
+    let total = cdf[n];
+    for (i, value) in cdf.iter_mut().enumerate() {
+        *value = if total > 0.0 {
+            *value / total
+        } else {
+            i as f32 / n as f32
+        };
+    }
+}
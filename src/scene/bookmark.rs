@@ -0,0 +1,77 @@
+use instant::Duration;
+use winit::keyboard::KeyCode;
+
+use super::{Camera, CameraAnimation, Keyframe};
+
+/// Viewpoint saved under a name, see [`Scene::bookmarks`](super::Scene::bookmarks).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub camera: Camera,
+}
+
+impl Bookmark {
+    /// Keys recalling the bookmarks in order, the first nine having one.
+    pub const KEYS: [KeyCode; 9] = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+
+    pub fn new(name: impl Into<String>, camera: Camera) -> Self {
+        Self {
+            name: name.into(),
+            camera,
+        }
+    }
+
+    /// Index of the bookmark recalled by `key`.
+    pub fn index_of_key(key: KeyCode) -> Option<usize> {
+        Self::KEYS.iter().position(|&other| other == key)
+    }
+}
+
+/// Move of the camera to a bookmark, easing in and out. The position, direction, field
+/// of view, focus and aperture are interpolated, the other settings of the bookmark are
+/// taken at once.
+#[derive(Clone, Debug)]
+pub struct CameraTransition {
+    path: CameraAnimation,
+    target: Camera,
+    elapsed: f32,
+}
+
+impl CameraTransition {
+    /// In seconds.
+    pub const DURATION: f32 = 1.0;
+
+    pub fn new(from: &Camera, to: &Camera) -> Self {
+        let mut path = CameraAnimation::default();
+        path.insert(Keyframe::new(0.0, from));
+        path.insert(Keyframe::new(Self::DURATION, to));
+        Self {
+            path,
+            target: *to,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Moves `camera` `dt` further along, true once it reached the bookmark.
+    pub fn update(&mut self, camera: &mut Camera, dt: Duration) -> bool {
+        self.elapsed += dt.as_secs_f32();
+        if self.elapsed >= Self::DURATION {
+            *camera = self.target;
+            return true;
+        }
+        let t = self.elapsed / Self::DURATION;
+        let eased = t * t * (3.0 - 2.0 * t);
+        *camera = self.path.camera_at(eased * Self::DURATION, &self.target);
+        false
+    }
+}
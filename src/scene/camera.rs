@@ -1,10 +1,15 @@
 use instant::Duration;
 use winit::{
     dpi::PhysicalPosition,
-    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
-    keyboard::{KeyCode, PhysicalKey},
+    event::{DeviceEvent, ElementState, MouseScrollDelta, Touch, WindowEvent},
+    keyboard::ModifiersState,
 };
 
+use super::{
+    Action, Aperture, Gesture, Input, InputMap, PhysicalCamera, Projection, Ray, TouchGestures,
+};
+use crate::object::Transform;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Camera {
     pub eye_pos: glm::Vec3,
@@ -15,10 +20,173 @@ pub struct Camera {
     pub aperture: f32,
     /// Focus distance must be a positive number.
     pub focus_distance: f32,
+    /// When set, `focus_distance` follows the depth of this point as the camera moves.
+    pub focus_point: Option<glm::Vec3>,
+    /// When set, replaces `vfov` and `aperture` and gives the exposure of the image.
+    pub physical: Option<PhysicalCamera>,
+    /// Only the perspective projection has depth of field.
+    pub projection: Projection,
+    /// Shape of the opening of radius `lens_radius`, see also `Scene::aperture_mask`.
+    pub aperture_shape: Aperture,
+    pub shutter: Shutter,
+    /// Move of the camera from time 0 to 1, rotating about the eye.
+    pub motion: Transform,
+}
+
+/// Part of the frame during which the shutter is open, objects and the camera moving
+/// over the frame from time 0 to 1. Each ray is traced at a time in between.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Shutter {
+    pub open: f32,
+    pub close: f32,
+}
+
+impl Shutter {
+    pub fn midpoint(&self) -> f32 {
+        0.5 * (self.open + self.close)
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+impl Camera {
+    pub const MIN_FOCUS_DISTANCE: f32 = 0.1;
+
+    /// Focuses on `point` until the focus is set by hand again.
+    pub fn focus_on(&mut self, point: glm::Vec3) {
+        self.focus_point = Some(point);
+        self.follow_focus_point();
+    }
+
+    /// Moves the focus plane, perpendicular to the view direction, back through
+    /// `focus_point` after the camera moved.
+    pub fn follow_focus_point(&mut self) {
+        if let Some(point) = self.focus_point {
+            let depth = glm::dot(&(point - self.eye_pos), &glm::normalize(&self.eye_dir));
+            self.focus_distance = depth.max(Self::MIN_FOCUS_DISTANCE);
+        }
+    }
+
+    /// Vertical field of view in degrees for an image of width over height `aspect`.
+    pub fn vfov(&self, aspect: f32) -> f32 {
+        self.physical
+            .map_or(self.vfov, |physical| physical.vfov(aspect))
+    }
+
+    pub fn lens_radius(&self) -> f32 {
+        self.physical
+            .map_or(0.5 * self.aperture, |physical| physical.lens_radius())
+    }
+
+    /// Factor applied to the radiance of the samples.
+    pub fn exposure(&self) -> f32 {
+        self.physical.map_or(1.0, |physical| physical.exposure())
+    }
+
+    /// Ray from the center of the lens through `uv`, the position on the image from its
+    /// bottom left corner in 0..=1, as traced by `get_ray` without depth of field at the
+    /// middle of the shutter interval. `None` outside of the image circle of a fisheye.
+    pub fn primary_ray(&self, viewport_size: (u32, u32), uv: glm::Vec2) -> Option<Ray> {
+        let time = self.shutter.midpoint();
+        let motion = Transform::identity().interpolate(&self.motion, time);
+        self.static_primary_ray(viewport_size, uv, time)
+            .map(|ray| Ray {
+                origin: self.eye_pos
+                    + motion.transform_vector(ray.origin - self.eye_pos)
+                    + motion.translation.xyz(),
+                direction: motion.transform_vector(ray.direction),
+                time,
+            })
+    }
+
+    /// `primary_ray` of the camera at rest.
+    fn static_primary_ray(
+        &self,
+        viewport_size: (u32, u32),
+        uv: glm::Vec2,
+        time: f32,
+    ) -> Option<Ray> {
+        let gpu_camera = GpuCamera::new(self, viewport_size);
+        let size = glm::vec2(viewport_size.0 as f32, viewport_size.1 as f32);
+        let (right, up, forward) = gpu_camera.level_basis();
+        match self.projection {
+            Projection::Perspective => {
+                let target = gpu_camera.lower_left_corner
+                    + uv.x * gpu_camera.horizontal
+                    + uv.y * gpu_camera.vertical;
+                Some(Ray {
+                    origin: self.eye_pos,
+                    direction: glm::normalize(&(target - self.eye_pos)),
+                    time,
+                })
+            }
+            Projection::Orthographic { .. } => Some(Ray {
+                origin: gpu_camera.lower_left_corner
+                    + uv.x * gpu_camera.horizontal
+                    + uv.y * gpu_camera.vertical,
+                direction: gpu_camera.w,
+                time,
+            }),
+            Projection::Fisheye { .. } => {
+                let p = (uv - glm::vec2(0.5, 0.5)).component_mul(&size) / (0.5 * size.min());
+                let r = p.magnitude();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * 0.5 * gpu_camera.fisheye_fov;
+                let phi = p.y.atan2(p.x);
+                let tilted_up = glm::cross(&right, &gpu_camera.w);
+                let direction = theta.cos() * gpu_camera.w
+                    + theta.sin() * (phi.cos() * right + phi.sin() * tilted_up);
+                Some(Ray {
+                    origin: self.eye_pos,
+                    direction,
+                    time,
+                })
+            }
+            Projection::Equirectangular => Some(Ray {
+                origin: self.eye_pos,
+                direction: panorama_direction(uv, right, up, forward),
+                time,
+            }),
+            Projection::StereoEquirectangular { ipd } => {
+                let left_eye = uv.y >= 0.5;
+                let uv = glm::vec2(uv.x, 2.0 * uv.y - if left_eye { 1.0 } else { 0.0 });
+                let phi = (uv.x - 0.5) * 2.0 * std::f32::consts::PI;
+                let side = phi.cos() * right - phi.sin() * forward;
+                let eye_offset = if left_eye { -0.5 } else { 0.5 } * ipd;
+                Some(Ray {
+                    origin: self.eye_pos + eye_offset * side,
+                    direction: panorama_direction(uv, right, up, forward),
+                    time,
+                })
+            }
+        }
+    }
+}
+
+/// How the mouse and keyboard move the camera.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControllerMode {
+    /// WASD moves the camera, right-drag turns it around itself.
+    Fly,
+    /// Right-drag turns the camera around `CameraController::pivot`, middle-drag pans
+    /// and the scroll wheel dollies towards the pivot.
+    Orbit,
+}
+
+#[derive(Debug, Clone)]
 pub struct CameraController {
+    mode: ControllerMode,
+    /// Point the orbit mode turns around.
+    pub pivot: glm::Vec3,
+    pub input_map: InputMap,
+    /// Modifiers held, which the bindings of `input_map` may need.
+    modifiers: ModifiersState,
+    fast_pressed: bool,
+    slow_pressed: bool,
+    touch: TouchGestures,
+    pan_pressed: bool,
+    pan_horizontal: f32,
+    pan_vertical: f32,
     updated: bool,
     amount_left: f32,
     amount_right: f32,
@@ -34,8 +202,26 @@ pub struct CameraController {
 }
 
 impl CameraController {
+    /// Fraction of the distance to the pivot panned per pixel of mouse motion.
+    const PAN_PER_PIXEL: f32 = 0.002;
+    /// Dolly factor per pixel of scrolling, also scales the focus distance in fly mode.
+    const DOLLY_PER_PIXEL: f32 = 0.001;
+    const MIN_ORBIT_DISTANCE: f32 = 0.01;
+    /// Keeps the orbit from going over the poles, where `camera.up` flips.
+    const MIN_ANGLE_TO_UP: f32 = 0.01;
+
     pub fn new(speed: f32, sensitivity: f32) -> Self {
         Self {
+            mode: ControllerMode::Fly,
+            pivot: glm::vec3(0.0, 0.0, 0.0),
+            input_map: InputMap::default(),
+            modifiers: ModifiersState::empty(),
+            fast_pressed: false,
+            slow_pressed: false,
+            touch: TouchGestures::default(),
+            pan_pressed: false,
+            pan_horizontal: 0.0,
+            pan_vertical: 0.0,
             updated: false,
             amount_left: 0.0,
             amount_right: 0.0,
@@ -51,57 +237,118 @@ impl CameraController {
         }
     }
 
+    pub fn mode(&self) -> ControllerMode {
+        self.mode
+    }
+
+    /// Switches to `mode`, the orbit starting around the point in focus.
+    pub fn set_mode(&mut self, mode: ControllerMode, camera: &Camera) {
+        if mode == ControllerMode::Orbit && self.mode != mode {
+            self.center_pivot(camera);
+        }
+        self.mode = mode;
+    }
+
+    /// Puts the pivot on the point in focus of `camera`, after it was moved from outside.
+    pub fn center_pivot(&mut self, camera: &Camera) {
+        self.pivot = camera.eye_pos + camera.focus_distance * glm::normalize(&camera.eye_dir);
+    }
+
+    /// Centers the pivot on the sphere at `center` of `radius` and moves the camera back
+    /// along its direction until the sphere fills the view of an image of width over
+    /// height `aspect`, focusing on its center.
+    pub fn frame(&mut self, camera: &mut Camera, center: glm::Vec3, radius: f32, aspect: f32) {
+        let half_height = (0.5 * camera.vfov(aspect).to_radians()).tan();
+        let half_fov = half_height.min(aspect * half_height).atan();
+        let distance = (radius / half_fov.sin()).max(Self::MIN_ORBIT_DISTANCE);
+        self.pivot = center;
+        camera.eye_dir = glm::normalize(&camera.eye_dir);
+        camera.eye_pos = center - distance * camera.eye_dir;
+        camera.focus_on(center);
+        if let Projection::Orthographic { height } = &mut camera.projection {
+            *height = 2.0 * radius / aspect.min(1.0);
+        }
+    }
+
     pub fn clear(&mut self) {
         self.updated = false;
         self.rotate_horizontal = 0.0;
         self.rotate_vertical = 0.0;
+        self.pan_horizontal = 0.0;
+        self.pan_vertical = 0.0;
         self.scroll = 0.0;
     }
 
-    pub fn process_keyboard(&mut self, key: KeyCode, state: ElementState) -> bool {
-        let amount = if state == ElementState::Pressed {
-            1.0
-        } else {
-            0.0
-        };
-        let s = match key {
-            KeyCode::KeyW | KeyCode::ArrowUp => {
-                self.amount_forward = amount;
-                true
+    /// Holds or releases the actions bound to `input`, true when there was one.
+    /// `mouse_pressed` follows [`Action::Rotate`].
+    pub fn process_input(
+        &mut self,
+        input: Input,
+        state: ElementState,
+        mouse_pressed: &mut bool,
+    ) -> bool {
+        let pressed = state == ElementState::Pressed;
+        let amount = if pressed { 1.0 } else { 0.0 };
+        let mut handled = false;
+        let actions: Vec<Action> = self
+            .input_map
+            .actions(input, state, self.modifiers)
+            .collect();
+        for action in actions {
+            match action {
+                Action::Forward => self.amount_forward = amount,
+                Action::Backward => self.amount_backward = amount,
+                Action::Left => self.amount_left = amount,
+                Action::Right => self.amount_right = amount,
+                Action::Up => self.amount_up = amount,
+                Action::Down => self.amount_down = amount,
+                Action::Rotate => *mouse_pressed = pressed,
+                Action::Pan => self.pan_pressed = pressed,
+                Action::Fast => self.fast_pressed = pressed,
+                Action::Slow => self.slow_pressed = pressed,
+                Action::FrameSelected | Action::Help => continue,
             }
-            KeyCode::KeyS | KeyCode::ArrowDown => {
-                self.amount_backward = amount;
-                true
-            }
-            KeyCode::KeyA | KeyCode::ArrowLeft => {
-                self.amount_left = amount;
-                true
-            }
-            KeyCode::KeyD | KeyCode::ArrowRight => {
-                self.amount_right = amount;
-                true
-            }
-            KeyCode::Space => {
-                self.amount_up = amount;
-                true
-            }
-            KeyCode::ShiftLeft => {
-                self.amount_down = amount;
-                true
-            }
-            _ => false,
-        };
-        self.updated = s;
-        s
+            handled = true;
+        }
+        self.updated = handled;
+        handled
+    }
+
+    /// Whether `event` presses a binding of `action`, for the actions handled outside of
+    /// the controller.
+    pub fn triggers(&self, event: &WindowEvent, action: Action) -> bool {
+        Input::from_event(event).is_some_and(|(input, state)| {
+            state == ElementState::Pressed
+                && self
+                    .input_map
+                    .actions(input, state, self.modifiers)
+                    .any(|other| other == action)
+        })
+    }
+
+    /// Factor of the moving speed, from the speed modifiers held.
+    fn speed_multiplier(&self) -> f32 {
+        let mut multiplier = 1.0;
+        if self.fast_pressed {
+            multiplier *= self.input_map.fast_multiplier;
+        }
+        if self.slow_pressed {
+            multiplier *= self.input_map.slow_multiplier;
+        }
+        multiplier
     }
 
     pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
         self.rotate_horizontal = mouse_dx as f32;
-        self.rotate_vertical = mouse_dy as f32;
+        self.rotate_vertical = if self.input_map.invert_y {
+            -mouse_dy as f32
+        } else {
+            mouse_dy as f32
+        };
     }
 
     pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
-        self.scroll = -match delta {
+        self.scroll -= match delta {
             // I'm assuming a line is about 100 pixels
             MouseScrollDelta::LineDelta(_, scroll) => scroll * 100.0,
             MouseScrollDelta::PixelDelta(PhysicalPosition { y: scroll, .. }) => *scroll as f32,
@@ -110,44 +357,57 @@ impl CameraController {
 
     pub fn handle_input(&mut self, event: &WindowEvent, mouse_pressed: &mut bool) {
         match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        physical_key: PhysicalKey::Code(key),
-                        state,
-                        ..
-                    },
-                ..
-            } => {
-                self.process_keyboard(*key, *state);
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            // Actions held while the window loses the focus would never be released
+            WindowEvent::Focused(false) => {
+                for action in Action::ALL {
+                    for binding in self.input_map.bindings(action).copied().collect::<Vec<_>>() {
+                        self.process_input(binding.input, ElementState::Released, mouse_pressed);
+                    }
+                }
             }
-            WindowEvent::MouseInput {
-                state: ElementState::Pressed,
-                button: MouseButton::Right,
-                ..
-            } => {
-                *mouse_pressed = true;
+            _ => {
+                if let Some((input, state)) = Input::from_event(event) {
+                    self.process_input(input, state, mouse_pressed);
+                }
             }
-            WindowEvent::MouseInput {
-                state: ElementState::Released,
-                button: MouseButton::Right,
-                ..
-            } => {
-                *mouse_pressed = false;
+        }
+    }
+
+    /// Moves the camera with the fingers on a touch screen as the mouse does in the
+    /// current mode, pinching to dolly. Returns where a finger tapped, see
+    /// [`TouchGestures::handle`] for `ignore`.
+    pub fn handle_touch(&mut self, touch: &Touch, ignore: bool) -> Option<glm::Vec2> {
+        match self.touch.handle(touch, ignore)? {
+            Gesture::Rotate(delta) => {
+                self.rotate_horizontal += delta.x;
+                self.rotate_vertical += if self.input_map.invert_y {
+                    -delta.y
+                } else {
+                    delta.y
+                };
             }
-            _ => {}
+            Gesture::PanZoom { pan, pinch } => {
+                self.pan_horizontal += pan.x;
+                self.pan_vertical += pan.y;
+                // Spreading the fingers brings the camera closer, like scrolling up
+                self.scroll -= pinch.ln() / Self::DOLLY_PER_PIXEL;
+            }
+            Gesture::Tap(position) => return Some(position),
         }
+        None
     }
 
     pub fn handle_mouse(&mut self, device_event: &DeviceEvent, mouse_pressed: bool) {
         match device_event {
-            DeviceEvent::MouseMotion { delta } => {
-                if mouse_pressed {
-                    self.process_mouse(delta.0, delta.1);
-                }
+            DeviceEvent::MouseMotion { delta } if mouse_pressed => {
+                self.process_mouse(delta.0, delta.1);
+            }
+            DeviceEvent::MouseMotion { delta } if self.pan_pressed => {
+                self.pan_horizontal += delta.0 as f32;
+                self.pan_vertical += delta.1 as f32;
             }
             DeviceEvent::MouseWheel { delta } => {
-                // TODO: Not behaving as expected
                 self.process_scroll(delta);
             }
             _ => {}
@@ -155,6 +415,15 @@ impl CameraController {
     }
 
     pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        match self.mode {
+            ControllerMode::Fly => self.update_fly(camera, dt),
+            ControllerMode::Orbit => self.update_orbit(camera, dt),
+        }
+        camera.follow_focus_point();
+    }
+
+    /// The scroll wheel moves the focus, releasing the point in focus.
+    fn update_fly(&mut self, camera: &mut Camera, dt: Duration) {
         let forward = self.amount_forward - self.amount_backward;
         let right = self.amount_right - self.amount_left;
         let up = self.amount_up - self.amount_down;
@@ -163,7 +432,7 @@ impl CameraController {
         let scroll = self.scroll;
 
         let dt = dt.as_secs_f32();
-        let speed = self.speed;
+        let speed = self.speed * self.speed_multiplier();
         let sensitivity = self.sensitivity;
 
         let forward = forward * speed * dt;
@@ -171,7 +440,6 @@ impl CameraController {
         let up = up * speed * dt;
         let rotate_horizontal = rotate_horizontal * sensitivity * dt;
         let rotate_vertical = rotate_vertical * sensitivity * dt;
-        let scroll = scroll * speed * dt;
 
         let forward = camera.eye_dir * forward;
         let right = glm::cross(&camera.eye_dir, &camera.up) * right;
@@ -186,8 +454,53 @@ impl CameraController {
         );
         camera.eye_dir = glm::normalize(&camera.eye_dir);
 
-        camera.focus_distance -= scroll;
-        camera.focus_distance = camera.focus_distance.max(0.1);
+        // Scroll events are not spread over frames, they are not scaled by the frame time
+        if scroll != 0.0 {
+            camera.focus_point = None;
+            camera.focus_distance *= (-scroll * Self::DOLLY_PER_PIXEL).exp();
+            camera.focus_distance = camera.focus_distance.max(Camera::MIN_FOCUS_DISTANCE);
+        }
+        self.clear();
+    }
+
+    /// Rotations and dolly keep the pivot at the same place in the view.
+    fn update_orbit(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let right_axis = glm::normalize(&glm::cross(&camera.eye_dir, &camera.up));
+        let up_axis = glm::cross(&right_axis, &camera.eye_dir);
+        let mut offset = camera.eye_pos - self.pivot;
+        let distance = offset.magnitude();
+
+        // The keys move the pivot along with the camera
+        let forward = (self.amount_forward - self.amount_backward) * camera.eye_dir;
+        let right = (self.amount_right - self.amount_left) * right_axis;
+        let up = (self.amount_up - self.amount_down) * camera.up;
+        let pan = Self::PAN_PER_PIXEL
+            * distance
+            * (self.pan_vertical * up_axis - self.pan_horizontal * right_axis);
+        self.pivot += (forward + right + up) * self.speed * self.speed_multiplier() * dt + pan;
+
+        let rotate_horizontal = self.rotate_horizontal * self.sensitivity * dt;
+        offset = glm::rotate_vec3(&offset, rotate_horizontal, &camera.up);
+        camera.eye_dir = glm::rotate_vec3(&camera.eye_dir, rotate_horizontal, &camera.up);
+
+        let rotate_vertical = self.rotate_vertical * self.sensitivity * dt;
+        let right_axis = glm::normalize(&glm::cross(&camera.eye_dir, &camera.up));
+        let eye_dir = glm::rotate_vec3(&camera.eye_dir, rotate_vertical, &right_axis);
+        let angle_to_up = glm::angle(&eye_dir, &camera.up);
+        if angle_to_up > Self::MIN_ANGLE_TO_UP
+            && angle_to_up < std::f32::consts::PI - Self::MIN_ANGLE_TO_UP
+        {
+            offset = glm::rotate_vec3(&offset, rotate_vertical, &right_axis);
+            camera.eye_dir = eye_dir;
+        }
+        camera.eye_dir = glm::normalize(&camera.eye_dir);
+
+        let dolly = (self.scroll * Self::DOLLY_PER_PIXEL).exp();
+        if offset.magnitude() * dolly > Self::MIN_ORBIT_DISTANCE {
+            offset *= dolly;
+        }
+        camera.eye_pos = self.pivot + offset;
         self.clear();
     }
 }
@@ -206,15 +519,35 @@ pub struct GpuCamera {
     v: glm::Vec3,
     lens_radius: f32,
     lower_left_corner: glm::Vec3,
-    _padding5: f32,
+    exposure: f32,
+    w: glm::Vec3,
+    /// A [`Projection::id`].
+    projection: u32,
+    /// In radians.
+    fisheye_fov: f32,
+    ods_ipd: f32,
+    aperture_blades: u32,
+    /// In radians.
+    aperture_rotation: f32,
+    aperture_squeeze: f32,
+    shutter_open: f32,
+    shutter_close: f32,
+    _padding: f32,
+    motion: Transform,
 }
 
 impl GpuCamera {
     pub fn new(camera: &Camera, viewport_size: (u32, u32)) -> Self {
-        let lens_radius = 0.5_f32 * camera.aperture;
+        let lens_radius = camera.lens_radius();
         let aspect = viewport_size.0 as f32 / viewport_size.1 as f32;
-        let theta = camera.vfov.to_radians();
-        let half_height = camera.focus_distance * (0.5_f32 * theta).tan();
+        let theta = camera.vfov(aspect).to_radians();
+        let mut half_height = camera.focus_distance * (0.5_f32 * theta).tan();
+        // The orthographic view is centered on the eye instead of the focus plane
+        let mut focus_distance = camera.focus_distance;
+        if let Projection::Orthographic { height } = camera.projection {
+            half_height = 0.5 * height;
+            focus_distance = 0.0;
+        }
         let half_width = aspect * half_height;
 
         let w = glm::normalize(&camera.eye_dir);
@@ -222,7 +555,7 @@ impl GpuCamera {
         let u = glm::cross(&w, &v);
 
         let lower_left_corner =
-            camera.eye_pos + camera.focus_distance * w - half_width * u - half_height * v;
+            camera.eye_pos + focus_distance * w - half_width * u - half_height * v;
         let horizontal = 2_f32 * half_width * u;
         let vertical = 2_f32 * half_height * v;
 
@@ -238,7 +571,43 @@ impl GpuCamera {
             v,
             lens_radius,
             lower_left_corner,
-            _padding5: 0_f32,
+            exposure: camera.exposure(),
+            w,
+            projection: camera.projection.id(),
+            fisheye_fov: match camera.projection {
+                Projection::Fisheye { fov } => fov.to_radians(),
+                _ => 0.0,
+            },
+            ods_ipd: match camera.projection {
+                Projection::StereoEquirectangular { ipd } => ipd,
+                _ => 0.0,
+            },
+            aperture_blades: camera.aperture_shape.blades,
+            aperture_rotation: camera.aperture_shape.rotation.to_radians(),
+            aperture_squeeze: camera.aperture_shape.squeeze,
+            shutter_open: camera.shutter.open,
+            shutter_close: camera.shutter.close,
+            _padding: 0.0,
+            motion: camera.motion,
         }
     }
+
+    /// Right, up and forward axes with a level forward, as `camera_level_basis` in the shader.
+    fn level_basis(&self) -> (glm::Vec3, glm::Vec3, glm::Vec3) {
+        let up = glm::normalize(&self.v);
+        let right = glm::normalize(&glm::cross(&self.w, &up));
+        (right, up, glm::cross(&up, &right))
+    }
+}
+
+/// Direction of `uv` on an equirectangular panorama, as `panorama_direction` in the shader.
+fn panorama_direction(
+    uv: glm::Vec2,
+    right: glm::Vec3,
+    up: glm::Vec3,
+    forward: glm::Vec3,
+) -> glm::Vec3 {
+    let phi = (uv.x - 0.5) * 2.0 * std::f32::consts::PI;
+    let elevation = (uv.y - 0.5) * std::f32::consts::PI;
+    elevation.cos() * (phi.sin() * right + phi.cos() * forward) + elevation.sin() * up
 }
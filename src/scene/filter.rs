@@ -0,0 +1,160 @@
+use std::f32::consts::PI;
+
+/// Pixel reconstruction filter, matches the order of `filter_table` in filter.wgsl.
+///
+/// The filters are separable and importance sampled: the offset of the camera ray from
+/// the pixel center follows the filter, so the image keeps a plain average of the samples.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFilter {
+    Box = 0,
+    Tent = 1,
+    Gaussian = 2,
+    Mitchell = 3,
+    BlackmanHarris = 4,
+}
+
+impl PixelFilter {
+    pub const ALL: [PixelFilter; 5] = [
+        PixelFilter::Box,
+        PixelFilter::Tent,
+        PixelFilter::Gaussian,
+        PixelFilter::Mitchell,
+        PixelFilter::BlackmanHarris,
+    ];
+
+    /// The filter of `ALL` called `name` in kebab case, e.g. `"blackman-harris"`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "box" => Some(PixelFilter::Box),
+            "tent" => Some(PixelFilter::Tent),
+            "gaussian" => Some(PixelFilter::Gaussian),
+            "mitchell" => Some(PixelFilter::Mitchell),
+            "blackman-harris" => Some(PixelFilter::BlackmanHarris),
+            _ => None,
+        }
+    }
+
+    /// Radius in pixels the filter is usually used with.
+    pub fn default_radius(self) -> f32 {
+        match self {
+            PixelFilter::Box => 0.5,
+            PixelFilter::Tent => 1.0,
+            PixelFilter::Gaussian => 1.5,
+            PixelFilter::Mitchell => 2.0,
+            PixelFilter::BlackmanHarris => 1.5,
+        }
+    }
+
+    /// 1D filter value at `x` in `[-1, 1]`, the radius scaled to one.
+    fn evaluate(self, x: f32) -> f32 {
+        match self {
+            PixelFilter::Box => 1.0,
+            PixelFilter::Tent => 1.0 - x.abs(),
+            PixelFilter::Gaussian => {
+                // Shifted down so that it reaches zero at the radius
+                let gaussian = |x: f32| (-4.5 * x * x).exp();
+                (gaussian(x) - gaussian(1.0)).max(0.0)
+            }
+            PixelFilter::Mitchell => {
+                // B = C = 1/3 over [-2, 2], negative between about 1.2 and 2
+                let x = 2.0 * x.abs();
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b))
+                        / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c))
+                        / 6.0
+                }
+            }
+            PixelFilter::BlackmanHarris => {
+                let t = 2.0 * PI * 0.5 * (x + 1.0);
+                0.35875 - 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() - 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+}
+
+/// Bins of the inverse CDF of each filter, must match `FILTER_BINS` in filter.wgsl.
+const FILTER_BINS: usize = 64;
+/// Cells used to integrate the filters when building the table.
+const INTEGRATION_STEPS: usize = 4096;
+
+/// Inverse CDFs of the absolute value of every [`PixelFilter`], sampled at `FILTER_BINS`
+/// regular steps. Each bin holds its lower offset in `[-1, 1]` and the weight of the
+/// samples falling in it: the sign of the filter there, normalized so that the weights
+/// average to one. Two bins are packed per `vec4`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuFilterTable {
+    bins: [[[f32; 4]; FILTER_BINS / 2]; PixelFilter::ALL.len()],
+}
+
+impl Default for GpuFilterTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GpuFilterTable {
+    pub fn new() -> Self {
+        let mut table = Self {
+            bins: [[[0.0; 4]; FILTER_BINS / 2]; PixelFilter::ALL.len()],
+        };
+        for filter in PixelFilter::ALL {
+            for (i, (offset, weight)) in Self::bins(filter).into_iter().enumerate() {
+                let packed = &mut table.bins[filter as usize][i / 2];
+                packed[2 * (i % 2)] = offset;
+                packed[2 * (i % 2) + 1] = weight;
+            }
+        }
+        table
+    }
+
+    fn bins(filter: PixelFilter) -> [(f32, f32); FILTER_BINS] {
+        let step = 2.0 / INTEGRATION_STEPS as f32;
+        let cell = |j: usize| -1.0 + (j as f32 + 0.5) * step;
+
+        let mut cdf = Vec::with_capacity(INTEGRATION_STEPS + 1);
+        cdf.push(0.0);
+        for j in 0..INTEGRATION_STEPS {
+            cdf.push(cdf[j] + filter.evaluate(cell(j)).abs());
+        }
+        let total = cdf[INTEGRATION_STEPS];
+
+        // Offset at which the CDF reaches `u`, interpolated within the integration cell
+        let inverse_cdf = |u: f32| {
+            let target = u * total;
+            let j = cdf
+                .partition_point(|&c| c <= target)
+                .clamp(1, INTEGRATION_STEPS)
+                - 1;
+            let mass = cdf[j + 1] - cdf[j];
+            let t = if mass > 0.0 {
+                (target - cdf[j]) / mass
+            } else {
+                0.0
+            };
+            (-1.0 + (j as f32 + t) * step).clamp(-1.0, 1.0)
+        };
+
+        let mut bins = [(0.0, 0.0); FILTER_BINS];
+        for (i, bin) in bins.iter_mut().enumerate() {
+            let lower = inverse_cdf(i as f32 / FILTER_BINS as f32);
+            let upper = inverse_cdf((i + 1) as f32 / FILTER_BINS as f32);
+            let sign = filter.evaluate(0.5 * (lower + upper)).signum();
+            *bin = (lower, sign);
+        }
+
+        // Every bin is as likely, the negative lobes are compensated by scaling all weights
+        let mean = bins.iter().map(|(_, sign)| sign).sum::<f32>() / FILTER_BINS as f32;
+        bins.map(|(offset, sign)| (offset, sign / mean))
+    }
+}
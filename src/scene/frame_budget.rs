@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameBudgetMode {
+    /// Keeps the frame time around the target so that the UI stays responsive.
+    Interactive,
+    /// Ignores the target and traces as many samples per frame as possible.
+    Final,
+    /// Keeps the samples per frame it was created with, for reproducible offline renders.
+    #[cfg(not(target_arch = "wasm32"))]
+    Fixed,
+}
+
+/// Chooses how many samples to trace per frame from the measured cost of a sample.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameBudget {
+    pub mode: FrameBudgetMode,
+    /// Frame time aimed for in [`FrameBudgetMode::Interactive`].
+    pub target: Duration,
+    samples_per_frame: u32,
+    /// See [`FrameBudget::set_limit`].
+    limit: u32,
+    sample_time: Option<Duration>,
+}
+
+impl FrameBudget {
+    /// Upper bound of the samples per frame, whatever the measured cost.
+    pub const MAX_SAMPLES_PER_FRAME: u32 = 256;
+    /// Frame time of [`FrameBudgetMode::Final`], only there to stay well below the time
+    /// after which drivers reset a device that does not answer.
+    const FINAL_FRAME_TIME: Duration = Duration::from_millis(250);
+
+    pub fn new(mode: FrameBudgetMode, target: Duration) -> Self {
+        Self {
+            mode,
+            target,
+            samples_per_frame: 1,
+            limit: Self::MAX_SAMPLES_PER_FRAME,
+            sample_time: None,
+        }
+    }
+
+    /// A [`FrameBudgetMode::Fixed`] budget tracing `samples_per_frame` every frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn fixed(samples_per_frame: u32) -> Self {
+        Self {
+            mode: FrameBudgetMode::Fixed,
+            target: Duration::ZERO,
+            samples_per_frame: samples_per_frame.clamp(1, Self::MAX_SAMPLES_PER_FRAME),
+            limit: Self::MAX_SAMPLES_PER_FRAME,
+            sample_time: None,
+        }
+    }
+
+    /// Lowers the samples per frame to at most `limit`, below
+    /// [`FrameBudget::MAX_SAMPLES_PER_FRAME`], for the tracers that cannot take as many.
+    pub fn set_limit(&mut self, limit: u32) {
+        self.limit = limit.clamp(1, Self::MAX_SAMPLES_PER_FRAME);
+        self.samples_per_frame = self.samples_per_frame.min(self.limit);
+    }
+
+    pub fn samples_per_frame(&self) -> u32 {
+        self.samples_per_frame
+    }
+
+    /// Last measured time per sample.
+    pub fn sample_time(&self) -> Option<Duration> {
+        self.sample_time
+    }
+
+    /// Records that `samples` samples took `elapsed` and adjusts the samples per frame,
+    /// at most doubling or halving them at once to smooth out noisy timings.
+    pub fn record(&mut self, elapsed: Duration, samples: u32) {
+        if samples == 0 {
+            return;
+        }
+        let sample_time = elapsed / samples;
+        self.sample_time = Some(sample_time);
+
+        let target = match self.mode {
+            FrameBudgetMode::Interactive => self.target,
+            FrameBudgetMode::Final => Self::FINAL_FRAME_TIME,
+            #[cfg(not(target_arch = "wasm32"))]
+            FrameBudgetMode::Fixed => return,
+        };
+        let ideal = target.as_secs_f64() / sample_time.as_secs_f64().max(1e-9);
+        let current = self.samples_per_frame as f64;
+        self.samples_per_frame = ideal
+            .clamp(current / 2.0, current * 2.0)
+            .clamp(1.0, self.limit as f64) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: Duration = Duration::from_millis(16);
+
+    #[test]
+    fn grows_towards_the_target() {
+        let mut budget = FrameBudget::new(FrameBudgetMode::Interactive, TARGET);
+        let mut samples = Vec::new();
+        for _ in 0..6 {
+            let spf = budget.samples_per_frame();
+            budget.record(spf * Duration::from_millis(1), spf);
+            samples.push(budget.samples_per_frame());
+        }
+        assert_eq!(samples, [2, 4, 8, 16, 16, 16]);
+    }
+
+    #[test]
+    fn shrinks_when_over_budget() {
+        let mut budget = FrameBudget::new(FrameBudgetMode::Interactive, TARGET);
+        budget.samples_per_frame = 64;
+        // Far over the target, at most halving at once
+        budget.record(Duration::from_secs(64), 64);
+        assert_eq!(budget.samples_per_frame(), 32);
+        // 4 ms per sample
+        budget.record(Duration::from_millis(128), 32);
+        assert_eq!(budget.samples_per_frame(), 16);
+        budget.record(Duration::from_millis(64), 16);
+        assert_eq!(budget.samples_per_frame(), 8);
+        budget.record(Duration::from_millis(32), 8);
+        assert_eq!(budget.samples_per_frame(), 4);
+        assert_eq!(budget.sample_time(), Some(Duration::from_millis(4)));
+    }
+
+    #[test]
+    fn stays_within_the_limits() {
+        let mut budget = FrameBudget::new(FrameBudgetMode::Final, TARGET);
+        for _ in 0..16 {
+            budget.record(Duration::ZERO, budget.samples_per_frame());
+        }
+        assert_eq!(
+            budget.samples_per_frame(),
+            FrameBudget::MAX_SAMPLES_PER_FRAME
+        );
+
+        budget.set_limit(8);
+        assert_eq!(budget.samples_per_frame(), 8);
+        budget.record(Duration::ZERO, 8);
+        assert_eq!(budget.samples_per_frame(), 8);
+
+        for _ in 0..8 {
+            budget.record(Duration::from_secs(10), budget.samples_per_frame());
+        }
+        assert_eq!(budget.samples_per_frame(), 1);
+
+        budget.set_limit(0);
+        assert_eq!(budget.samples_per_frame(), 1);
+    }
+
+    #[test]
+    fn fixed_ignores_the_timings() {
+        let mut budget = FrameBudget::fixed(12);
+        budget.record(Duration::from_secs(10), 12);
+        assert_eq!(budget.samples_per_frame(), 12);
+        assert_eq!(budget.sample_time(), Some(Duration::from_secs(10) / 12));
+        budget.record(Duration::ZERO, 12);
+        assert_eq!(budget.samples_per_frame(), 12);
+
+        assert_eq!(FrameBudget::fixed(0).samples_per_frame(), 1);
+        assert_eq!(
+            FrameBudget::fixed(100_000).samples_per_frame(),
+            FrameBudget::MAX_SAMPLES_PER_FRAME
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io::{self, BufRead, Write};
+
+use winit::{
+    event::{ElementState, KeyEvent, MouseButton, WindowEvent},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
+};
+
+/// What the controls do, each bound to any number of inputs by an [`InputMap`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+    /// Turns the camera while held and the mouse moves.
+    Rotate,
+    /// Pans the orbit while held and the mouse moves.
+    Pan,
+    /// Multiplies the moving speed by [`InputMap::fast_multiplier`] while held.
+    Fast,
+    /// Multiplies the moving speed by [`InputMap::slow_multiplier`] while held.
+    Slow,
+    FrameSelected,
+    Help,
+}
+
+impl Action {
+    pub const ALL: [Self; 12] = [
+        Self::Forward,
+        Self::Backward,
+        Self::Left,
+        Self::Right,
+        Self::Up,
+        Self::Down,
+        Self::Rotate,
+        Self::Pan,
+        Self::Fast,
+        Self::Slow,
+        Self::FrameSelected,
+        Self::Help,
+    ];
+
+    /// Name in the config file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Backward => "backward",
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Rotate => "rotate",
+            Self::Pan => "pan",
+            Self::Fast => "fast",
+            Self::Slow => "slow",
+            Self::FrameSelected => "frame_selected",
+            Self::Help => "help",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Forward => "Move forward",
+            Self::Backward => "Move backward",
+            Self::Left => "Move left",
+            Self::Right => "Move right",
+            Self::Up => "Move up",
+            Self::Down => "Move down",
+            Self::Rotate => "Turn the camera, around the pivot in orbit mode (drag)",
+            Self::Pan => "Pan in orbit mode (drag)",
+            Self::Fast => "Move faster (hold)",
+            Self::Slow => "Move slower (hold)",
+            Self::FrameSelected => "Frame the selected object",
+            Self::Help => "Show the controls",
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// Key, by its position on a US keyboard, or mouse button.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Input {
+    /// Input changing state in `event` along with its new state, `None` for key repeats.
+    pub fn from_event(event: &WindowEvent) -> Option<(Self, ElementState)> {
+        match event {
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => Some((Self::Key(*key), *state)),
+            WindowEvent::MouseInput { button, state, .. } => Some((Self::Mouse(*button), *state)),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse(name: &str) -> Option<Self> {
+        let mouse = match name {
+            "MouseLeft" => Some(MouseButton::Left),
+            "MouseRight" => Some(MouseButton::Right),
+            "MouseMiddle" => Some(MouseButton::Middle),
+            "MouseBack" => Some(MouseButton::Back),
+            "MouseForward" => Some(MouseButton::Forward),
+            _ => None,
+        };
+        mouse.map(Self::Mouse).or_else(|| {
+            KEY_CODES
+                .into_iter()
+                .find(|key| format!("{:?}", key) == name)
+                .map(Self::Key)
+        })
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "{:?}", key),
+            Self::Mouse(MouseButton::Other(button)) => write!(f, "Mouse{}", button),
+            Self::Mouse(button) => write!(f, "Mouse{:?}", button),
+        }
+    }
+}
+
+/// Input triggering an action while the modifiers are held, more may be held as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub modifiers: ModifiersState,
+    pub input: Input,
+}
+
+impl Binding {
+    pub fn key(key: KeyCode) -> Self {
+        Self {
+            modifiers: ModifiersState::empty(),
+            input: Input::Key(key),
+        }
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Self {
+            modifiers: ModifiersState::empty(),
+            input: Input::Mouse(button),
+        }
+    }
+
+    /// Parses the modifiers and the input joined by `+`, as `Ctrl+Shift+KeyZ`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse(text: &str) -> Option<Self> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let input = Input::parse(parts.pop()?)?;
+        let mut modifiers = ModifiersState::empty();
+        for part in parts {
+            modifiers |= match part {
+                "Shift" => ModifiersState::SHIFT,
+                "Ctrl" => ModifiersState::CONTROL,
+                "Alt" => ModifiersState::ALT,
+                "Super" => ModifiersState::SUPER,
+                _ => return None,
+            };
+        }
+        Some(Self { modifiers, input })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (ModifiersState::SHIFT, "Shift"),
+            (ModifiersState::CONTROL, "Ctrl"),
+            (ModifiersState::ALT, "Alt"),
+            (ModifiersState::SUPER, "Super"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.input)
+    }
+}
+
+/// Bindings of the actions of the camera controller, loaded from a config file with one
+/// setting per line:
+///
+/// ```text
+/// forward = KeyW, ArrowUp
+/// rotate = MouseRight, Alt+MouseLeft
+/// fast_multiplier = 4
+/// invert_y = true
+/// ```
+///
+/// The keys are named after [`KeyCode`], the mouse buttons `MouseLeft`, `MouseRight` and
+/// `MouseMiddle`. Keys go by their position, `KeyW` being the Z key of an AZERTY layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputMap {
+    bindings: Vec<(Action, Binding)>,
+    pub fast_multiplier: f32,
+    pub slow_multiplier: f32,
+    /// Moving the mouse up turns the camera down.
+    pub invert_y: bool,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self {
+            bindings: vec![
+                (Action::Forward, Binding::key(KeyCode::KeyW)),
+                (Action::Forward, Binding::key(KeyCode::ArrowUp)),
+                (Action::Backward, Binding::key(KeyCode::KeyS)),
+                (Action::Backward, Binding::key(KeyCode::ArrowDown)),
+                (Action::Left, Binding::key(KeyCode::KeyA)),
+                (Action::Left, Binding::key(KeyCode::ArrowLeft)),
+                (Action::Right, Binding::key(KeyCode::KeyD)),
+                (Action::Right, Binding::key(KeyCode::ArrowRight)),
+                (Action::Up, Binding::key(KeyCode::Space)),
+                (Action::Down, Binding::key(KeyCode::ShiftLeft)),
+                (Action::Rotate, Binding::mouse(MouseButton::Right)),
+                (Action::Pan, Binding::mouse(MouseButton::Middle)),
+                (Action::Fast, Binding::key(KeyCode::ControlLeft)),
+                (Action::Slow, Binding::key(KeyCode::AltLeft)),
+                (Action::FrameSelected, Binding::key(KeyCode::KeyF)),
+                (Action::Help, Binding::key(KeyCode::F1)),
+            ],
+            fast_multiplier: 4.0,
+            slow_multiplier: 0.25,
+            invert_y: false,
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, action: Action) -> impl Iterator<Item = &Binding> {
+        self.bindings
+            .iter()
+            .filter(move |(other, _)| *other == action)
+            .map(|(_, binding)| binding)
+    }
+
+    /// Actions bound to `input`. Presses need the modifiers of the binding to be held
+    /// among `modifiers`, releases don't, so that a modifier let go first does not
+    /// leave the action held.
+    pub fn actions(
+        &self,
+        input: Input,
+        state: ElementState,
+        modifiers: ModifiersState,
+    ) -> impl Iterator<Item = Action> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, binding)| {
+                binding.input == input
+                    && (state == ElementState::Released || modifiers.contains(binding.modifiers))
+            })
+            .map(|(action, _)| *action)
+    }
+
+    /// The bindings of `action` separated by commas, as in the config file.
+    pub fn describe(&self, action: Action) -> String {
+        self.bindings(action)
+            .map(Binding::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        for action in Action::ALL {
+            writeln!(writer, "{} = {}", action.name(), self.describe(action))?;
+        }
+        writeln!(writer, "fast_multiplier = {}", self.fast_multiplier)?;
+        writeln!(writer, "slow_multiplier = {}", self.slow_multiplier)?;
+        writeln!(writer, "invert_y = {}", self.invert_y)
+    }
+
+    /// Reads the settings written by [`InputMap::write`]. The actions missing keep their
+    /// default bindings, an action without any is unbound. Lines starting with `#` are
+    /// skipped.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read(reader: impl BufRead) -> io::Result<Self> {
+        let mut input_map = Self::default();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: &str| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} on line {}", message, number + 1),
+                )
+            };
+            let (name, value) = line
+                .split_once('=')
+                .map(|(name, value)| (name.trim(), value.trim()))
+                .ok_or_else(|| invalid("missing ="))?;
+            match name {
+                "fast_multiplier" => {
+                    input_map.fast_multiplier =
+                        value.parse().map_err(|_| invalid("invalid number"))?
+                }
+                "slow_multiplier" => {
+                    input_map.slow_multiplier =
+                        value.parse().map_err(|_| invalid("invalid number"))?
+                }
+                "invert_y" => {
+                    input_map.invert_y = value.parse().map_err(|_| invalid("invalid boolean"))?
+                }
+                _ => {
+                    let action =
+                        Action::from_name(name).ok_or_else(|| invalid("unknown action"))?;
+                    input_map.bindings.retain(|(other, _)| *other != action);
+                    for binding in value.split(',').map(str::trim) {
+                        if binding.is_empty() {
+                            continue;
+                        }
+                        let binding =
+                            Binding::parse(binding).ok_or_else(|| invalid("invalid binding"))?;
+                        input_map.bindings.push((action, binding));
+                    }
+                }
+            }
+        }
+        Ok(input_map)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: &std::path::Path) -> io::Result<()> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: &std::path::Path) -> io::Result<Self> {
+        Self::read(io::BufReader::new(std::fs::File::open(path)?))
+    }
+}
+
+/// Keys which can be bound, by the name of their [`KeyCode`].
+#[cfg(not(target_arch = "wasm32"))]
+const KEY_CODES: [KeyCode; 100] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+    KeyCode::Digit0,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+    KeyCode::Numpad0,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::NumpadAdd,
+    KeyCode::NumpadSubtract,
+    KeyCode::NumpadMultiply,
+    KeyCode::NumpadDivide,
+    KeyCode::NumpadDecimal,
+    KeyCode::NumpadEnter,
+    KeyCode::F1,
+    KeyCode::F2,
+    KeyCode::F3,
+    KeyCode::F4,
+    KeyCode::F5,
+    KeyCode::F6,
+    KeyCode::F7,
+    KeyCode::F8,
+    KeyCode::F9,
+    KeyCode::F10,
+    KeyCode::F11,
+    KeyCode::F12,
+    KeyCode::ArrowUp,
+    KeyCode::ArrowDown,
+    KeyCode::ArrowLeft,
+    KeyCode::ArrowRight,
+    KeyCode::PageUp,
+    KeyCode::PageDown,
+    KeyCode::Home,
+    KeyCode::End,
+    KeyCode::Insert,
+    KeyCode::Delete,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Enter,
+    KeyCode::Backspace,
+    KeyCode::CapsLock,
+    KeyCode::ShiftLeft,
+    KeyCode::ShiftRight,
+    KeyCode::ControlLeft,
+    KeyCode::ControlRight,
+    KeyCode::AltLeft,
+    KeyCode::AltRight,
+    KeyCode::SuperLeft,
+    KeyCode::SuperRight,
+    KeyCode::Backquote,
+    KeyCode::Minus,
+    KeyCode::Equal,
+    KeyCode::BracketLeft,
+    KeyCode::BracketRight,
+    KeyCode::Backslash,
+    KeyCode::IntlBackslash,
+    KeyCode::Semicolon,
+    KeyCode::Quote,
+    KeyCode::Comma,
+    KeyCode::Period,
+    KeyCode::Slash,
+    KeyCode::Escape,
+];
@@ -0,0 +1,42 @@
+use crate::object::{Mesh, ObjectType, Sphere};
+
+/// An emissive sphere or triangle that can be sampled directly by the wavefront tracer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable, PartialEq)]
+pub struct GpuLight {
+    kind: u32,
+    index: u32,
+    material_index: u32,
+    area: f32,
+    /// Index in `Scene::objects`, whose motion moves the light.
+    object: u32,
+}
+
+impl GpuLight {
+    pub fn sphere(index: u32, sphere: &Sphere) -> Self {
+        Self {
+            kind: ObjectType::Sphere as u32,
+            index,
+            material_index: index,
+            area: 4.0 * std::f32::consts::PI * sphere.radius * sphere.radius,
+            object: index,
+        }
+    }
+
+    pub fn triangle(object: u32, index: u32, material_index: u32, surface: &Mesh) -> Self {
+        let v0 = surface.vertices[0].xyz();
+        let e1 = surface.vertices[1].xyz() - v0;
+        let e2 = surface.vertices[2].xyz() - v0;
+        Self {
+            kind: ObjectType::Mesh as u32,
+            index,
+            material_index,
+            area: 0.5 * glm::cross(&e1, &e2).magnitude(),
+            object,
+        }
+    }
+
+    pub fn area(&self) -> f32 {
+        self.area
+    }
+}
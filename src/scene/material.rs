@@ -20,10 +20,21 @@ impl Texture {
         }
     }
 
+    /// A `width` by `height` image from its rows of `data`, from the top row down.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(dimensions: (u32, u32), data: Vec<[f32; 3]>) -> Self {
+        assert_eq!(data.len(), (dimensions.0 * dimensions.1) as usize);
+        Self { dimensions, data }
+    }
+
     pub fn as_slice(&self) -> &[[f32; 3]] {
         &self.data
     }
 
+    pub fn as_mut_slice(&mut self) -> &mut [[f32; 3]] {
+        &mut self.data
+    }
+
     pub fn dimensions(&self) -> (u32, u32) {
         self.dimensions
     }
@@ -31,10 +42,23 @@ impl Texture {
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Material {
-    Lambertian { albedo: Texture },
-    Metal { albedo: Texture, fuzz: f32 },
-    Dialectric { ref_idx: f32 },
-    DiffuseLight { emit: Texture },
+    Lambertian {
+        albedo: Texture,
+    },
+    // Only the scenes of the native app are made of them, the web app renders the
+    // Cornell box
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    Metal {
+        albedo: Texture,
+        fuzz: f32,
+    },
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    Dialectric {
+        ref_idx: f32,
+    },
+    DiffuseLight {
+        emit: Texture,
+    },
 }
 
 #[repr(C)]
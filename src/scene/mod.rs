@@ -1,10 +1,48 @@
+mod animation;
+pub use animation::{CameraAnimation, Keyframe};
+
+mod aperture;
+pub use aperture::Aperture;
+
+mod bookmark;
+pub use bookmark::{Bookmark, CameraTransition};
+
 mod camera;
-pub use camera::{Camera, CameraController, GpuCamera};
+pub use camera::{Camera, CameraController, ControllerMode, GpuCamera, Shutter};
+
+mod filter;
+pub use filter::{GpuFilterTable, PixelFilter};
+
+mod frame_budget;
+pub use frame_budget::{FrameBudget, FrameBudgetMode};
+
+mod input;
+pub use input::{Action, Input, InputMap};
+
+mod light;
+pub use light::GpuLight;
 
 mod material;
 pub use material::{GpuMaterial, Material, Texture};
 
-use crate::object::{self, rotate, scale, translate, Mesh, Object, ObjectType, Sphere};
+mod physical_camera;
+pub use physical_camera::PhysicalCamera;
+
+mod pick;
+pub use pick::{Pick, Ray};
+
+mod projection;
+pub use projection::Projection;
+
+mod progress;
+pub use progress::{Converged, Progress, RenderState, StopCriteria};
+
+mod touch;
+pub use touch::{Gesture, TouchGestures};
+
+use crate::object::{rotate, scale, translate, Mesh, Object, ObjectType, Sphere, Transform};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::utils::Rng;
 
 #[derive(Clone, Debug)]
 pub struct Scene {
@@ -13,6 +51,13 @@ pub struct Scene {
     pub spheres: Vec<Sphere>,
     pub meshes: Vec<Mesh>,
     pub camera: Camera,
+    /// Image of the lens opening replacing the shape of `camera.aperture_shape`, its
+    /// luminance weighting the lens.
+    pub aperture_mask: Option<Texture>,
+    /// Viewpoints to go back to, the first nine recalled with the number keys.
+    pub bookmarks: Vec<Bookmark>,
+    /// Moves `camera` from the input, left out of the comparison as only the camera it
+    /// moves changes the image.
     pub camera_controller: CameraController,
     pub render_param: RenderParam,
     pub frame_data: FrameData,
@@ -21,16 +66,87 @@ pub struct Scene {
 impl PartialEq for Scene {
     fn eq(&self, other: &Self) -> bool {
         self.materials == other.materials
+            && self.objects == other.objects
             && self.spheres == other.spheres
+            && self.meshes == other.meshes
             && self.camera == other.camera
+            && self.aperture_mask == other.aperture_mask
             && self.frame_data == other.frame_data
-            && self.camera_controller == other.camera_controller
     }
 }
 
 impl Scene {
-    #[allow(dead_code)]
+    /// Flattens the materials into their GPU representation along with the global
+    /// texture data they index into.
+    pub fn gpu_materials(&self) -> (Vec<GpuMaterial>, Vec<[f32; 3]>) {
+        let mut global_texture_data = Vec::new();
+        let material_data = self
+            .materials
+            .iter()
+            .map(|material| GpuMaterial::new(material, &mut global_texture_data))
+            .collect();
+        (material_data, global_texture_data)
+    }
+
+    /// Collects the emissive spheres and triangles, walking the objects the same way
+    /// `check_intersection` does in the shader.
+    pub fn gpu_lights(&self) -> Vec<GpuLight> {
+        let is_light = |material_index: u32| {
+            matches!(
+                self.materials.get(material_index as usize),
+                Some(Material::DiffuseLight { .. })
+            )
+        };
+
+        let mut lights = Vec::new();
+        let mut mesh_offset = 0;
+        for (i, object) in self.objects.iter().enumerate() {
+            let i = i as u32;
+            if object.count > 1 {
+                if is_light(object.id) {
+                    for j in 0..object.count {
+                        let index = mesh_offset + i + j;
+                        if let Some(surface) = self.meshes.get(index as usize) {
+                            lights.push(GpuLight::triangle(i, index, object.id, surface));
+                        }
+                    }
+                }
+                mesh_offset += object.count - 1;
+            } else if object.obj_type == ObjectType::Sphere as u32 {
+                if let Some(sphere) = self.spheres.get(i as usize) {
+                    if is_light(i) {
+                        lights.push(GpuLight::sphere(i, sphere));
+                    }
+                }
+            } else if let Some(surface) = self.meshes.get(i as usize) {
+                if is_light(object.id) {
+                    lights.push(GpuLight::triangle(i, i, object.id, surface));
+                }
+            }
+        }
+        lights.retain(|light| light.area() > 0.0);
+        lights
+    }
+
+    /// Names of the scenes built by [`Scene::from_name`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub const NAMES: [&'static str; 3] = ["cornell", "oneweek", "motion-blur"];
+
+    /// The scene called `name`, one of [`Scene::NAMES`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_name(name: &str, render_param: RenderParam, frame_data: FrameData) -> Option<Self> {
+        match name {
+            "cornell" => Some(Self::cornell_scene(render_param, frame_data)),
+            "oneweek" => Some(Self::raytracing_scene_oneweek(render_param, frame_data)),
+            "motion-blur" => Some(Self::motion_blur_scene(render_param, frame_data)),
+            _ => None,
+        }
+    }
+
+    /// The small spheres are placed from `render_param.seed`.
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn raytracing_scene_oneweek(render_param: RenderParam, frame_data: FrameData) -> Self {
+        let mut rng = Rng::from_seed(render_param.seed);
         let mut spheres = Vec::new();
         let mut materials = Vec::new();
 
@@ -42,30 +158,30 @@ impl Scene {
         spheres.push(Sphere::new(glm::vec3(0.0, -1000.0, 0.0), 1000.0));
 
         for (a, b) in (-11..11).flat_map(|a| (-11..11).map(move |b| (a, b))) {
-            let choose_mat = rand::random::<f32>();
+            let choose_mat = rng.next_f32();
             let center = glm::vec3(
-                a as f32 + 0.9 * rand::random::<f32>(),
+                a as f32 + 0.9 * rng.next_f32(),
                 0.2,
-                b as f32 + 0.9 * rand::random::<f32>(),
+                b as f32 + 0.9 * rng.next_f32(),
             );
 
             if (center - glm::vec3(4.0, 0.2, 0.0)).magnitude() > 0.9 {
                 let sphere_material = if choose_mat < 0.8 {
                     Material::Lambertian {
                         albedo: Texture::new_from_color(glm::vec3(
-                            rand::random::<f32>() * rand::random::<f32>(),
-                            rand::random::<f32>() * rand::random::<f32>(),
-                            rand::random::<f32>() * rand::random::<f32>(),
+                            rng.next_f32() * rng.next_f32(),
+                            rng.next_f32() * rng.next_f32(),
+                            rng.next_f32() * rng.next_f32(),
                         )),
                     }
                 } else if choose_mat < 0.95 {
                     Material::Metal {
                         albedo: Texture::new_from_color(glm::vec3(
-                            0.5 * (1.0 + rand::random::<f32>()),
-                            0.5 * (1.0 + rand::random::<f32>()),
-                            0.5 * (1.0 + rand::random::<f32>()),
+                            0.5 * (1.0 + rng.next_f32()),
+                            0.5 * (1.0 + rng.next_f32()),
+                            0.5 * (1.0 + rng.next_f32()),
                         )),
-                        fuzz: rand::random::<f32>() * 0.5,
+                        fuzz: rng.next_f32() * 0.5,
                     }
                 } else {
                     Material::Dialectric { ref_idx: 1.5 }
@@ -100,12 +216,18 @@ impl Scene {
             vfov: 20.0,
             aperture: 0.6,
             focus_distance: 10.0,
+            focus_point: None,
+            physical: None,
+            projection: Projection::Perspective,
+            aperture_shape: Aperture::default(),
+            shutter: Shutter::default(),
+            motion: Transform::identity(),
         };
 
         let objects: Vec<Object> = spheres
             .iter()
             .enumerate()
-            .map(|(i, _)| Object::new(i as u32, object::ObjectType::Sphere, None))
+            .map(|(i, _)| Object::new(i as u32, ObjectType::Sphere, None))
             .collect();
 
         Self {
@@ -116,10 +238,41 @@ impl Scene {
             spheres,
             render_param,
             frame_data,
+            aperture_mask: None,
+            bookmarks: vec![Bookmark::new("Start", camera)],
             camera_controller: CameraController::new(4.0, 0.4),
         }
     }
 
+    /// The scene of [`Scene::raytracing_scene_oneweek`] set in motion as in "The Next
+    /// Week": while the shutter is open, the small diffuse spheres bounce and the metal
+    /// sphere swings around the glass one.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn motion_blur_scene(render_param: RenderParam, frame_data: FrameData) -> Self {
+        let mut scene = Self::raytracing_scene_oneweek(render_param, frame_data);
+        // Drawn apart from the placement of the spheres, which stays the same
+        let mut rng = Rng::from_seed(!render_param.seed);
+        for (sphere, material) in scene.spheres.iter_mut().zip(&scene.materials) {
+            if sphere.radius == 0.2 && matches!(material, Material::Lambertian { .. }) {
+                sphere.center1.y += 0.5 * rng.next_f32();
+            }
+        }
+
+        // The metal sphere comes last
+        let metal = scene.objects.len() - 1;
+        scene.objects[metal] = scene.objects[metal].with_motion(
+            Transform::identity(),
+            Transform::rotation_about(glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 1.0, 0.0), 15.0),
+        );
+
+        scene.camera.shutter = Shutter {
+            open: 0.0,
+            close: 1.0,
+        };
+        scene.bookmarks = vec![Bookmark::new("Start", scene.camera)];
+        scene
+    }
+
     pub fn cornell_scene(render_param: RenderParam, frame_data: FrameData) -> Self {
         let mut materials = Vec::new();
         let mut objects = Vec::new();
@@ -149,7 +302,7 @@ impl Scene {
 
         let mut back_wall = Mesh::quad();
         translate(&mut back_wall, glm::vec3(0.0, 0.0, -1.0));
-        back_wall.iter().for_each(|m| meshes.push(m.clone()));
+        back_wall.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(0, ObjectType::Mesh, Some(2)));
 
         let mut left_wall = Mesh::quad();
@@ -162,7 +315,7 @@ impl Scene {
                 glm::vec4(0.5, 0.0, 0.0, 1.0),
             ]
         }
-        left_wall.iter().for_each(|m| meshes.push(m.clone()));
+        left_wall.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(1, ObjectType::Mesh, Some(2)));
 
         let mut right_wall: Vec<Mesh> = Mesh::quad();
@@ -175,7 +328,7 @@ impl Scene {
                 glm::vec4(-0.5, 0.0, 0.0, 1.0),
             ]
         }
-        right_wall.iter().for_each(|m| meshes.push(m.clone()));
+        right_wall.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(2, ObjectType::Mesh, Some(2)));
 
         let mut ceiling = Mesh::quad();
@@ -188,7 +341,7 @@ impl Scene {
                 glm::vec4(0.0, -0.5, 0.0, 1.0),
             ]
         }
-        ceiling.iter().for_each(|m| meshes.push(m.clone()));
+        ceiling.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(3, ObjectType::Mesh, Some(2)));
 
         let mut floor = Mesh::quad();
@@ -201,7 +354,7 @@ impl Scene {
                 glm::vec4(0.0, 0.5, 0.0, 1.0),
             ]
         }
-        floor.iter().for_each(|m| meshes.push(m.clone()));
+        floor.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(4, ObjectType::Mesh, Some(2)));
 
         let mut ceiling_light = Mesh::quad();
@@ -215,14 +368,14 @@ impl Scene {
                 glm::vec4(0.0, -0.5, 0.0, 1.0),
             ]
         }
-        ceiling_light.iter().for_each(|m| meshes.push(m.clone()));
+        ceiling_light.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(5, ObjectType::Mesh, Some(2)));
 
         let mut box1 = Mesh::cube();
         scale(&mut box1, glm::vec3(0.3, 0.3, 0.3));
         rotate(&mut box1, 70., glm::vec3(0.0, 1.0, 0.0));
         translate(&mut box1, glm::vec3(0.3, -0.699, 0.3));
-        box1.iter().for_each(|m| meshes.push(m.clone()));
+        box1.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(6, ObjectType::Mesh, Some(box1.len())));
 
         let mut rectangle_box = Mesh::cube();
@@ -230,7 +383,7 @@ impl Scene {
         rotate(&mut rectangle_box, 15., glm::vec3(0.0, 1.0, 0.0));
         translate(&mut rectangle_box, glm::vec3(-0.3, -0.399, -0.2));
 
-        rectangle_box.iter().for_each(|m| meshes.push(m.clone()));
+        rectangle_box.iter().for_each(|m| meshes.push(*m));
         objects.push(Object::new(7, ObjectType::Mesh, Some(rectangle_box.len())));
 
         let camera = Camera {
@@ -240,7 +393,35 @@ impl Scene {
             vfov: 30.0,
             aperture: 0.0,
             focus_distance: 10.0,
+            focus_point: None,
+            physical: None,
+            projection: Projection::Perspective,
+            aperture_shape: Aperture::default(),
+            shutter: Shutter::default(),
+            motion: Transform::identity(),
         };
+        let bookmarks = vec![
+            Bookmark::new("Front", camera),
+            Bookmark::new(
+                "Boxes",
+                Camera {
+                    eye_pos: glm::vec3(0.0, 0.3, 3.5),
+                    eye_dir: glm::vec3(0.0, -0.25, -1.0),
+                    vfov: 35.0,
+                    focus_distance: 3.5,
+                    ..camera
+                },
+            ),
+            Bookmark::new(
+                "Left corner",
+                Camera {
+                    eye_pos: glm::vec3(0.8, 0.6, 1.5),
+                    eye_dir: glm::vec3(-0.6, -0.35, -1.0),
+                    vfov: 50.0,
+                    ..camera
+                },
+            ),
+        ];
 
         Self {
             objects,
@@ -250,10 +431,13 @@ impl Scene {
             spheres: vec![Sphere::empty()],
             render_param,
             frame_data,
+            aperture_mask: None,
+            bookmarks,
             camera_controller: CameraController::new(4.0, 0.4),
         }
     }
 
+    #[allow(dead_code)]
     pub fn teapot_scene(render_param: RenderParam, frame_data: FrameData) -> Self {
         let mut materials = Vec::new();
         let mut objects = Vec::new();
@@ -284,6 +468,12 @@ impl Scene {
             vfov: 45.0,
             aperture: 0.0,
             focus_distance: 1.0,
+            focus_point: None,
+            physical: None,
+            projection: Projection::Perspective,
+            aperture_shape: Aperture::default(),
+            shutter: Shutter::default(),
+            motion: Transform::identity(),
         };
 
         Self {
@@ -294,6 +484,8 @@ impl Scene {
             spheres: vec![Sphere::empty()],
             render_param,
             frame_data,
+            aperture_mask: None,
+            bookmarks: vec![Bookmark::new("Start", camera)],
             camera_controller: CameraController::new(4.0, 0.4),
         }
     }
@@ -307,21 +499,138 @@ pub struct RenderParam {
     pub total_samples: u32,
     pub clear_samples: u32,
     pub max_depth: u32,
+    /// Bounces after which paths are randomly terminated based on their throughput.
+    pub rr_min_depth: u32,
+    /// A [`SamplerKind`] as `u32`.
+    pub sampler_kind: u32,
+    /// When non zero, pixels get samples according to their estimated error.
+    pub adaptive: u32,
+    /// Relative error of the pixel mean below which a pixel stops receiving samples.
+    pub noise_threshold: f32,
+    /// Samples taken before the error of a pixel is trusted.
+    pub adaptive_min_samples: u32,
+    /// When non zero, the sample count of each pixel is displayed instead of the image.
+    pub show_heatmap: u32,
+    /// A [`PixelFilter`] as `u32`.
+    pub filter_kind: u32,
+    /// Radius of the pixel filter, in pixels.
+    pub filter_radius: f32,
+    /// Seed of the samples and of the procedural scenes, the same seed and settings
+    /// always give the same image.
+    pub seed: u32,
+    /// Maximum luminance of the light reaching the camera after one bounce, zero
+    /// disables the clamp. Removes fireflies at the cost of darker highlights.
+    pub clamp_direct: f32,
+    /// Same as `clamp_direct` for the light reaching the camera after more bounces.
+    pub clamp_indirect: f32,
+    /// Standard deviations above the mean luminance of their pixel beyond which samples
+    /// are scaled down, zero disables the rejection.
+    pub outlier_sigma: f32,
+}
+
+/// Source of the pixel, lens, BSDF and light samples, matches `SAMPLER_*` in sampler.wgsl.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    Independent = 0,
+    Sobol = 1,
+    OwenSobol = 2,
+    BlueNoise = 3,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Sobol,
+        SamplerKind::OwenSobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    /// The sampler of `ALL` called `name` in kebab case, e.g. `"owen-sobol"`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "independent" => Some(SamplerKind::Independent),
+            "sobol" => Some(SamplerKind::Sobol),
+            "owen-sobol" => Some(SamplerKind::OwenSobol),
+            "blue-noise" => Some(SamplerKind::BlueNoise),
+            _ => None,
+        }
+    }
+}
+
+impl Default for RenderParam {
+    fn default() -> Self {
+        Self {
+            samples_per_pixel: 1,
+            max_depth: 30,
+            rr_min_depth: 3,
+            sampler_kind: SamplerKind::Independent as u32,
+            adaptive: 0,
+            noise_threshold: 0.01,
+            adaptive_min_samples: 16,
+            show_heatmap: 0,
+            filter_kind: PixelFilter::Box as u32,
+            filter_radius: PixelFilter::Box.default_radius(),
+            samples_max_per_pixel: 1000,
+            total_samples: 0,
+            clear_samples: 0,
+            seed: 0,
+            clamp_direct: 0.0,
+            clamp_indirect: 0.0,
+            outlier_sigma: 0.0,
+        }
+    }
 }
 
 impl RenderParam {
-    pub fn update(&mut self) {
-        if self.total_samples == 0 {
-            self.total_samples += self.samples_per_pixel;
-            self.clear_samples = 1;
-        } else if self.total_samples <= self.samples_max_per_pixel {
-            self.total_samples += self.samples_per_pixel;
-            self.clear_samples = 0;
+    /// Usual values of the firefly suppression settings when they get enabled.
+    pub const DEFAULT_CLAMP_DIRECT: f32 = 100.0;
+    pub const DEFAULT_CLAMP_INDIRECT: f32 = 10.0;
+    pub const DEFAULT_OUTLIER_SIGMA: f32 = 4.0;
+
+    /// Maximum factor applied to `samples_per_pixel` for the noisiest pixels,
+    /// matches `ADAPTIVE_MAX_BOOST` in adaptive.wgsl.
+    pub const ADAPTIVE_MAX_BOOST: u32 = 4;
+
+    /// Upper bound of the samples a pixel can take in one frame.
+    pub fn max_samples_per_frame(&self) -> u32 {
+        self.samples_per_pixel * self.boost()
+    }
+
+    /// Most a pixel can multiply `samples_per_pixel` by in one frame.
+    pub fn boost(&self) -> u32 {
+        if self.adaptive != 0 {
+            Self::ADAPTIVE_MAX_BOOST
         } else {
-            self.samples_per_pixel = 0;
-            self.clear_samples = 0;
+            1
         }
     }
+
+    pub fn sampler(&self) -> SamplerKind {
+        SamplerKind::ALL
+            .into_iter()
+            .find(|kind| *kind as u32 == self.sampler_kind)
+            .unwrap_or(SamplerKind::Independent)
+    }
+
+    pub fn filter(&self) -> PixelFilter {
+        PixelFilter::ALL
+            .into_iter()
+            .find(|kind| *kind as u32 == self.filter_kind)
+            .unwrap_or(PixelFilter::Box)
+    }
+
+    /// Prepares the next frame: restarts the accumulation when `total_samples` was reset
+    /// and takes the samples allowed by `budget`, without going over `samples_max_per_pixel`.
+    pub fn update(&mut self, budget: &FrameBudget) {
+        self.clear_samples = (self.total_samples == 0) as u32;
+        let remaining = self
+            .samples_max_per_pixel
+            .saturating_sub(self.total_samples);
+        self.samples_per_pixel = budget.samples_per_frame().min(remaining);
+        self.total_samples += self.samples_per_pixel;
+    }
 }
 
 #[repr(C)]
@@ -337,3 +646,61 @@ impl PartialEq for FrameData {
         self.width == other.width && self.height == other.height
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{
+        dpi::PhysicalPosition,
+        event::{DeviceId, Touch, TouchPhase, WindowEvent},
+        keyboard::ModifiersState,
+    };
+
+    fn scene() -> Scene {
+        let frame_data = FrameData {
+            width: 64,
+            height: 48,
+            index: 0,
+        };
+        Scene::cornell_scene(RenderParam::default(), frame_data)
+    }
+
+    #[test]
+    fn held_modifiers_leave_the_scene_unchanged() {
+        let before = scene();
+        let mut after = before.clone();
+        let mut mouse_pressed = false;
+        let event = WindowEvent::ModifiersChanged(ModifiersState::SHIFT.into());
+        after
+            .camera_controller
+            .handle_input(&event, &mut mouse_pressed);
+        after.camera_controller.input_map.invert_y = !after.camera_controller.input_map.invert_y;
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn orbit_mode_and_pivot_leave_the_scene_unchanged() {
+        let before = scene();
+        let mut after = before.clone();
+        after
+            .camera_controller
+            .set_mode(ControllerMode::Orbit, &after.camera);
+        after.camera_controller.pivot = glm::vec3(1.0, 2.0, 3.0);
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn finger_down_leaves_the_scene_unchanged() {
+        let before = scene();
+        let mut after = before.clone();
+        let touch = Touch {
+            device_id: DeviceId::dummy(),
+            phase: TouchPhase::Started,
+            location: PhysicalPosition::new(10.0, 10.0),
+            force: None,
+            id: 0,
+        };
+        after.camera_controller.handle_touch(&touch, false);
+        assert_eq!(before, after);
+    }
+}
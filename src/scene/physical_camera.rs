@@ -0,0 +1,67 @@
+/// Real-camera settings from which the field of view, lens radius and exposure of a
+/// [`Camera`](super::Camera) are derived, one scene unit being a metre.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhysicalCamera {
+    /// In millimetres.
+    pub focal_length: f32,
+    /// Focal length over the diameter of the aperture.
+    pub f_number: f32,
+    /// In millimetres, the sensor height follows the aspect ratio of the image.
+    pub sensor_width: f32,
+    pub iso: f32,
+    /// Shutter time, in seconds.
+    pub shutter: f32,
+    /// Added to the exposure of the settings above, in stops.
+    pub exposure_compensation: f32,
+}
+
+impl Default for PhysicalCamera {
+    /// A 50 mm lens at f/2.8 on a full frame sensor, ISO 100 at 1/60 s.
+    fn default() -> Self {
+        Self {
+            focal_length: 50.0,
+            f_number: 2.8,
+            sensor_width: Self::FULL_FRAME_WIDTH,
+            iso: 100.0,
+            shutter: 1.0 / 60.0,
+            exposure_compensation: 0.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    pub const FULL_FRAME_WIDTH: f32 = 36.0;
+
+    /// The default settings with the focal length giving `vfov`, in degrees.
+    pub fn with_vfov(vfov: f32, aspect: f32) -> Self {
+        let mut camera = Self::default();
+        let sensor_height = camera.sensor_width / aspect;
+        camera.focal_length = 0.5 * sensor_height / (0.5 * vfov.to_radians()).tan();
+        camera
+    }
+
+    /// Vertical field of view in degrees for an image of width over height `aspect`.
+    pub fn vfov(&self, aspect: f32) -> f32 {
+        let sensor_height = self.sensor_width / aspect;
+        2.0 * (0.5 * sensor_height / self.focal_length)
+            .atan()
+            .to_degrees()
+    }
+
+    /// Radius of the aperture, in metres.
+    pub fn lens_radius(&self) -> f32 {
+        0.5 * self.focal_length / self.f_number * 1e-3
+    }
+
+    /// Exposure value of the settings at ISO 100, not counting the compensation.
+    pub fn ev100(&self) -> f32 {
+        (self.f_number * self.f_number / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    /// Factor from the scene radiance to the image, with the saturation based sensitivity
+    /// of a sensor that does not clip before 1.
+    pub fn exposure(&self) -> f32 {
+        // https://seblagarde.files.wordpress.com/2015/07/course_notes_moving_frostbite_to_pbr_v32.pdf
+        2_f32.powf(self.exposure_compensation) / (1.2 * 2_f32.powf(self.ev100()))
+    }
+}
@@ -0,0 +1,192 @@
+use super::Scene;
+use crate::object::{Mesh, ObjectType, Sphere};
+
+// Same bounds as `MIN_T` and `MAX_T` in raytracing.wgsl
+const MIN_T: f32 = 0.001;
+const MAX_T: f32 = 1000.0;
+const EPSILON: f32 = 0.0001;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: glm::Vec3,
+    pub direction: glm::Vec3,
+    /// Time in the frame the ray is traced at, see [`Shutter`](super::Shutter).
+    pub time: f32,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> glm::Vec3 {
+        self.origin + t * self.direction
+    }
+}
+
+/// Closest geometry hit by a ray cast with [`Scene::pick`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pick {
+    /// Distance along the ray, in units of its direction.
+    pub t: f32,
+    pub point: glm::Vec3,
+    /// Index in `Scene::objects` of the object that was hit.
+    pub object: usize,
+}
+
+impl Scene {
+    /// Casts `ray` on the CPU against the spheres and triangles, walking the objects
+    /// the same way `check_intersection` does in the shader.
+    pub fn pick(&self, ray: &Ray) -> Option<Pick> {
+        let mut closest: Option<Pick> = None;
+        for (i, triangles) in self.object_ranges() {
+            let t_max = closest.map_or(MAX_T, |pick| pick.t);
+            // The transforms are rigid, distances along the ray stay the same
+            let transform = self.objects[i].transform(ray.time);
+            let local_ray = Ray {
+                origin: transform.inverse_transform_point(ray.origin),
+                direction: transform.inverse_transform_vector(ray.direction),
+                time: ray.time,
+            };
+            let t = if self.is_sphere(i) {
+                self.spheres
+                    .get(i)
+                    .and_then(|sphere| hit_sphere(sphere, &local_ray, t_max))
+            } else {
+                self.meshes
+                    .get(triangles)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|triangle| hit_triangle(triangle, &local_ray, t_max))
+                    .min_by(f32::total_cmp)
+            };
+            if let Some(t) = t {
+                closest = Some(Pick {
+                    t,
+                    point: ray.at(t),
+                    object: i,
+                });
+            }
+        }
+        closest
+    }
+
+    /// Axis aligned bounds of the object `index` of `Scene::objects`, at the start and
+    /// the end of its motion.
+    pub fn object_bounds(&self, index: usize) -> Option<(glm::Vec3, glm::Vec3)> {
+        [0.0, 1.0]
+            .into_iter()
+            .filter_map(|time| self.object_bounds_at(index, time))
+            .reduce(union)
+    }
+
+    fn object_bounds_at(&self, index: usize, time: f32) -> Option<(glm::Vec3, glm::Vec3)> {
+        let (_, triangles) = self.object_ranges().nth(index)?;
+        let transform = self.objects[index].transform(time);
+        if self.is_sphere(index) {
+            let sphere = self.spheres.get(index)?;
+            let center = transform.transform_point(sphere.center(time));
+            let radius = glm::vec3(sphere.radius, sphere.radius, sphere.radius);
+            return Some((center - radius, center + radius));
+        }
+        self.meshes
+            .get(triangles)?
+            .iter()
+            .flat_map(|triangle| triangle.vertices.iter())
+            .map(|vertex| transform.transform_point(vertex.xyz()))
+            .map(|vertex| (vertex, vertex))
+            .reduce(union)
+    }
+
+    /// Frames the object `index` of `Scene::objects` with the camera controller, or the
+    /// whole scene without one, for an image of width over height `aspect`.
+    pub fn frame_object(&mut self, index: Option<usize>, aspect: f32) {
+        let bounds = match index {
+            Some(index) => self.object_bounds(index),
+            None => (0..self.objects.len())
+                .filter_map(|index| self.object_bounds(index))
+                .reduce(union),
+        };
+        if let Some((min, max)) = bounds {
+            let center = 0.5 * (min + max);
+            let radius = 0.5 * (max - min).magnitude();
+            self.camera_controller
+                .frame(&mut self.camera, center, radius, aspect);
+        }
+    }
+
+    /// Focuses the camera on the geometry seen at `uv`, the position on an image of
+    /// `viewport_size` from its bottom left corner in 0..=1. Returns what was hit.
+    pub fn focus_at(&mut self, viewport_size: (u32, u32), uv: glm::Vec2) -> Option<Pick> {
+        let ray = self.camera.primary_ray(viewport_size, uv)?;
+        let pick = self.pick(&ray)?;
+        self.camera.focus_on(pick.point);
+        Some(pick)
+    }
+
+    /// Index of each object with the range of `Scene::meshes` holding its triangles,
+    /// meshes of more than one triangle shift the triangles of the following objects.
+    fn object_ranges(&self) -> impl Iterator<Item = (usize, std::ops::Range<usize>)> + '_ {
+        let mut mesh_offset = 0;
+        self.objects.iter().enumerate().map(move |(i, object)| {
+            let count = object.count as usize;
+            if count > 1 {
+                let start = mesh_offset + i;
+                mesh_offset += count - 1;
+                (i, start..start + count)
+            } else {
+                (i, i..i + 1)
+            }
+        })
+    }
+
+    fn is_sphere(&self, index: usize) -> bool {
+        let object = &self.objects[index];
+        object.count <= 1 && object.obj_type == ObjectType::Sphere as u32
+    }
+}
+
+fn union(
+    (min_a, max_a): (glm::Vec3, glm::Vec3),
+    (min_b, max_b): (glm::Vec3, glm::Vec3),
+) -> (glm::Vec3, glm::Vec3) {
+    (glm::min2(&min_a, &min_b), glm::max2(&max_a, &max_b))
+}
+
+fn hit_sphere(sphere: &Sphere, ray: &Ray, t_max: f32) -> Option<f32> {
+    let oc = ray.origin - sphere.center(ray.time);
+    let a = ray.direction.dot(&ray.direction);
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.dot(&oc) - sphere.radius * sphere.radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+        .into_iter()
+        .find(|t| (MIN_T..=t_max).contains(t))
+}
+
+/// Möller–Trumbore, as `hit_triangle` in the shader.
+fn hit_triangle(triangle: &Mesh, ray: &Ray, t_max: f32) -> Option<f32> {
+    let [v0, v1, v2] = triangle.vertices.map(|vertex| vertex.xyz());
+    let e1 = v1 - v0;
+    let e2 = v2 - v0;
+    let h = ray.direction.cross(&e2);
+    let a = e1.dot(&h);
+    if a.abs() < EPSILON {
+        return None;
+    }
+
+    let f = 1.0 / a;
+    let s = ray.origin - v0;
+    let u = f * s.dot(&h);
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(&e1);
+    let v = f * ray.direction.dot(&q);
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = f * e2.dot(&q);
+    (t > MIN_T && t < t_max).then_some(t)
+}
@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+use super::RenderParam;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderState {
+    /// Samples are traced every frame.
+    Running,
+    /// Stopped by the user, the accumulated image is kept.
+    Paused,
+    /// Stopped because one of the [`StopCriteria`] was met.
+    Converged,
+}
+
+/// The stop criterion that ended a render.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// Every pixel took `samples_max_per_pixel` samples.
+    Samples,
+    /// The rendering time reached [`StopCriteria::max_time`].
+    Time,
+    /// No pixel is above the noise threshold anymore.
+    Noise,
+}
+
+/// When a render stops, on top of `RenderParam::samples_max_per_pixel`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StopCriteria {
+    /// Rendering time after which the render stops, pauses excluded.
+    pub max_time: Option<Duration>,
+    /// Stops once the estimated error of every pixel is below `RenderParam::noise_threshold`.
+    pub noise: bool,
+}
+
+/// Sent once when a render converges.
+#[derive(Clone, Copy, Debug)]
+pub struct Converged {
+    pub reason: StopReason,
+    pub samples: u32,
+    pub elapsed: Duration,
+}
+
+/// Tracks whether samples are still traced and checks the [`StopCriteria`].
+#[derive(Clone, Debug)]
+pub struct Progress {
+    pub criteria: StopCriteria,
+    state: RenderState,
+    stop_reason: Option<StopReason>,
+    /// Rendering time of the current accumulation.
+    elapsed: Duration,
+    /// Pixels above the noise threshold, as last read back from the GPU.
+    noisy_pixels: Option<u32>,
+    /// Incremented whenever the accumulation restarts, see [`Progress::restarts`].
+    restarts: u32,
+}
+
+impl Progress {
+    pub fn new(criteria: StopCriteria) -> Self {
+        Self {
+            criteria,
+            state: RenderState::Running,
+            stop_reason: None,
+            elapsed: Duration::ZERO,
+            noisy_pixels: None,
+            restarts: 0,
+        }
+    }
+
+    pub fn state(&self) -> RenderState {
+        self.state
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn noisy_pixels(&self) -> Option<u32> {
+        self.noisy_pixels
+    }
+
+    /// Number of restarts of the accumulation so far, lets a noisy pixel count read
+    /// back late be told apart from one of the current accumulation.
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == RenderState::Running
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == RenderState::Running {
+            self.state = RenderState::Paused;
+        }
+    }
+
+    /// Continues a paused render, or a converged one whose criteria were relaxed.
+    pub fn resume(&mut self) {
+        self.state = RenderState::Running;
+        self.stop_reason = None;
+    }
+
+    /// Clears the accumulation and starts rendering again.
+    pub fn restart(&mut self, render_param: &mut RenderParam) {
+        render_param.total_samples = 0;
+        self.on_restart();
+    }
+
+    /// Continues a render restored from a checkpoint taken after `elapsed` of rendering.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn restore(&mut self, elapsed: Duration) {
+        self.on_restart();
+        self.elapsed = elapsed;
+    }
+
+    fn on_restart(&mut self) {
+        self.resume();
+        self.elapsed = Duration::ZERO;
+        self.noisy_pixels = None;
+        self.restarts = self.restarts.wrapping_add(1);
+    }
+
+    /// Records a noisy pixel count read back from the GPU, `restarts` being the value
+    /// of [`Progress::restarts`] when its frame was dispatched.
+    pub fn record_noisy_pixels(&mut self, noisy_pixels: u32, restarts: u32) {
+        if restarts == self.restarts {
+            self.noisy_pixels = Some(noisy_pixels);
+        }
+    }
+
+    /// Accounts for a frame of `dt` and checks the stop criteria. Any reset of
+    /// `total_samples`, such as a scene change, restarts the render even when paused.
+    /// Returns the convergence event on the frame the render converges.
+    pub fn update(&mut self, render_param: &RenderParam, dt: Duration) -> Option<Converged> {
+        if render_param.total_samples == 0 && (self.elapsed > Duration::ZERO || !self.is_running())
+        {
+            self.on_restart();
+        }
+        if !self.is_running() {
+            return None;
+        }
+        self.elapsed += dt;
+
+        let reason = if render_param.total_samples >= render_param.samples_max_per_pixel {
+            StopReason::Samples
+        } else if self
+            .criteria
+            .max_time
+            .is_some_and(|max_time| self.elapsed >= max_time)
+        {
+            StopReason::Time
+        } else if self.criteria.noise && self.noisy_pixels == Some(0) {
+            StopReason::Noise
+        } else {
+            return None;
+        };
+
+        self.state = RenderState::Converged;
+        self.stop_reason = Some(reason);
+        Some(Converged {
+            reason,
+            samples: render_param.total_samples,
+            elapsed: self.elapsed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    fn render_param(total_samples: u32) -> RenderParam {
+        RenderParam {
+            total_samples,
+            samples_max_per_pixel: 100,
+            ..Default::default()
+        }
+    }
+
+    fn criteria(max_time: Option<Duration>, noise: bool) -> StopCriteria {
+        StopCriteria { max_time, noise }
+    }
+
+    #[test]
+    fn converges_once_on_samples() {
+        let mut progress = Progress::new(criteria(None, false));
+        assert_eq!(progress.state(), RenderState::Running);
+        assert!(progress.update(&render_param(0), FRAME).is_none());
+        assert!(progress.update(&render_param(99), FRAME).is_none());
+        assert_eq!(progress.elapsed(), 2 * FRAME);
+
+        let converged = progress.update(&render_param(100), FRAME).unwrap();
+        assert_eq!(converged.reason, StopReason::Samples);
+        assert_eq!(converged.samples, 100);
+        assert_eq!(converged.elapsed, 3 * FRAME);
+        assert_eq!(progress.state(), RenderState::Converged);
+        assert_eq!(progress.stop_reason(), Some(StopReason::Samples));
+
+        // No second event and no more time counted
+        assert!(progress.update(&render_param(100), FRAME).is_none());
+        assert_eq!(progress.elapsed(), 3 * FRAME);
+    }
+
+    #[test]
+    fn pause_stops_the_clock() {
+        let mut progress = Progress::new(criteria(Some(3 * FRAME), false));
+        progress.update(&render_param(1), FRAME);
+        progress.pause();
+        assert_eq!(progress.state(), RenderState::Paused);
+        for _ in 0..10 {
+            assert!(progress.update(&render_param(1), FRAME).is_none());
+        }
+        assert_eq!(progress.elapsed(), FRAME);
+
+        progress.resume();
+        assert!(progress.update(&render_param(2), FRAME).is_none());
+        let converged = progress.update(&render_param(3), FRAME).unwrap();
+        assert_eq!(converged.reason, StopReason::Time);
+        assert_eq!(converged.elapsed, 3 * FRAME);
+
+        // Pausing does not undo the convergence
+        progress.pause();
+        assert_eq!(progress.state(), RenderState::Converged);
+    }
+
+    #[test]
+    fn noise_ignores_counts_of_an_earlier_accumulation() {
+        let mut progress = Progress::new(criteria(None, true));
+        let restarts = progress.restarts();
+        progress.update(&render_param(1), FRAME);
+        progress.record_noisy_pixels(12, restarts);
+        assert!(progress.update(&render_param(2), FRAME).is_none());
+
+        let mut param = render_param(2);
+        progress.restart(&mut param);
+        assert_eq!(param.total_samples, 0);
+        assert_eq!(progress.noisy_pixels(), None);
+        progress.record_noisy_pixels(0, restarts);
+        assert!(progress.update(&render_param(1), FRAME).is_none());
+
+        progress.record_noisy_pixels(0, progress.restarts());
+        let converged = progress.update(&render_param(2), FRAME).unwrap();
+        assert_eq!(converged.reason, StopReason::Noise);
+    }
+
+    #[test]
+    fn reset_samples_restart_the_render() {
+        let mut progress = Progress::new(criteria(None, false));
+        progress.update(&render_param(100), FRAME);
+        assert_eq!(progress.state(), RenderState::Converged);
+        let restarts = progress.restarts();
+
+        // A scene edit resets the samples, even a converged or paused render starts again
+        assert!(progress.update(&render_param(0), FRAME).is_none());
+        assert_eq!(progress.state(), RenderState::Running);
+        assert_eq!(progress.stop_reason(), None);
+        assert_eq!(progress.elapsed(), FRAME);
+        assert_eq!(progress.restarts(), restarts + 1);
+
+        progress.pause();
+        assert!(progress.update(&render_param(0), FRAME).is_none());
+        assert_eq!(progress.state(), RenderState::Running);
+        assert_eq!(progress.restarts(), restarts + 2);
+    }
+
+    #[test]
+    fn restore_keeps_the_checkpoint_time() {
+        let mut progress = Progress::new(criteria(Some(Duration::from_secs(60)), false));
+        progress.update(&render_param(100), FRAME);
+        progress.restore(Duration::from_secs(59));
+        assert_eq!(progress.state(), RenderState::Running);
+        assert_eq!(progress.elapsed(), Duration::from_secs(59));
+
+        let mut param = render_param(50);
+        param.samples_max_per_pixel = 1000;
+        assert!(progress.update(&param, FRAME).is_none());
+        let converged = progress.update(&param, Duration::from_secs(1)).unwrap();
+        assert_eq!(converged.reason, StopReason::Time);
+    }
+}
@@ -0,0 +1,56 @@
+/// How camera rays leave the camera, see `get_ray` in raytracing.wgsl.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Thin lens perspective of `Camera::vfov`.
+    Perspective,
+    /// Parallel rays along the view direction, `height` being the height of the view in
+    /// scene units.
+    Orthographic { height: f32 },
+    /// Equidistant fisheye, the angle to the view direction grows linearly up to `fov` / 2
+    /// degrees on the border of the image circle.
+    Fisheye { fov: f32 },
+    /// 360° by 180° panorama around the camera, with a level horizon.
+    Equirectangular,
+    /// Omni-directional stereo panorama, the left eye on the top half of the image and
+    /// the right eye on the bottom half, the eyes being `ipd` scene units apart.
+    StereoEquirectangular { ipd: f32 },
+}
+
+impl Projection {
+    pub const ALL: [Projection; 5] = [
+        Projection::Perspective,
+        Projection::Orthographic { height: 2.0 },
+        Projection::Fisheye { fov: 180.0 },
+        Projection::Equirectangular,
+        Projection::StereoEquirectangular { ipd: 0.064 },
+    ];
+
+    /// Matches `PROJECTION_*` in raytracing.wgsl.
+    pub fn id(&self) -> u32 {
+        match self {
+            Projection::Perspective => 0,
+            Projection::Orthographic { .. } => 1,
+            Projection::Fisheye { .. } => 2,
+            Projection::Equirectangular => 3,
+            Projection::StereoEquirectangular { .. } => 4,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "Perspective",
+            Projection::Orthographic { .. } => "Orthographic",
+            Projection::Fisheye { .. } => "Fisheye",
+            Projection::Equirectangular => "Equirectangular",
+            Projection::StereoEquirectangular { .. } => "Stereo equirectangular",
+        }
+    }
+
+    /// The projection of `ALL` called `name` in kebab case, e.g. `"stereo-equirectangular"`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|projection| projection.name().replace(' ', "-").to_lowercase() == name)
+    }
+}
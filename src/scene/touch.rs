@@ -0,0 +1,181 @@
+use winit::event::{Touch, TouchPhase};
+
+/// Camera move made by the fingers on a touch screen, in physical pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    /// One finger dragged, like the mouse while rotating.
+    Rotate(glm::Vec2),
+    /// Two fingers moved, their midpoint by `pan` and their distance scaled by `pinch`.
+    PanZoom { pan: glm::Vec2, pinch: f32 },
+    /// One finger lifted close to where it touched.
+    Tap(glm::Vec2),
+}
+
+/// Turns the touch events of a window into gestures, following the fingers down.
+#[derive(Clone, Debug, Default)]
+pub struct TouchGestures {
+    /// Position of each finger down, by touch id, in the order they touched.
+    touches: Vec<(u64, glm::Vec2)>,
+    /// Distance the finger went since it touched while it was the only one, infinite
+    /// once another finger touched.
+    travel: f32,
+}
+
+impl TouchGestures {
+    /// Farthest a finger goes for its touch to stay a tap, in physical pixels.
+    pub const TAP_DISTANCE: f32 = 10.0;
+
+    /// Follows `touch`. A finger touching while `ignore` is set, as over the UI, is left
+    /// out until it is lifted.
+    pub fn handle(&mut self, touch: &Touch, ignore: bool) -> Option<Gesture> {
+        let position = glm::vec2(touch.location.x as f32, touch.location.y as f32);
+        let index = self.touches.iter().position(|(id, _)| *id == touch.id);
+        match (touch.phase, index) {
+            (TouchPhase::Started, None) if !ignore => {
+                self.touches.push((touch.id, position));
+                self.travel = if self.touches.len() == 1 {
+                    0.0
+                } else {
+                    f32::INFINITY
+                };
+                None
+            }
+            (TouchPhase::Moved, Some(index)) => {
+                if self.touches.len() == 1 {
+                    let delta = position - self.touches[0].1;
+                    self.touches[0].1 = position;
+                    self.travel += delta.magnitude();
+                    return Some(Gesture::Rotate(delta));
+                }
+                // Fingers past the second one are followed but do not move the camera
+                let before = self.pair();
+                self.touches[index].1 = position;
+                let after = self.pair();
+                (index < 2).then(|| {
+                    let (midpoint, distance) = after;
+                    let (midpoint_before, distance_before) = before;
+                    Gesture::PanZoom {
+                        pan: midpoint - midpoint_before,
+                        pinch: if distance_before > 0.0 {
+                            distance / distance_before
+                        } else {
+                            1.0
+                        },
+                    }
+                })
+            }
+            (TouchPhase::Ended | TouchPhase::Cancelled, Some(index)) => {
+                self.touches.remove(index);
+                let tap = touch.phase == TouchPhase::Ended
+                    && self.touches.is_empty()
+                    && self.travel < Self::TAP_DISTANCE;
+                tap.then_some(Gesture::Tap(position))
+            }
+            _ => None,
+        }
+    }
+
+    /// Midpoint and distance of the first two fingers.
+    fn pair(&self) -> (glm::Vec2, f32) {
+        let (a, b) = (self.touches[0].1, self.touches[1].1);
+        (0.5 * (a + b), (b - a).magnitude())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{dpi::PhysicalPosition, event::DeviceId};
+
+    fn touch(id: u64, phase: TouchPhase, x: f64, y: f64) -> Touch {
+        Touch {
+            device_id: DeviceId::dummy(),
+            phase,
+            location: PhysicalPosition::new(x, y),
+            force: None,
+            id,
+        }
+    }
+
+    #[test]
+    fn short_touch_is_a_tap() {
+        let mut gestures = TouchGestures::default();
+        gestures.handle(&touch(0, TouchPhase::Started, 100.0, 100.0), false);
+        gestures.handle(&touch(0, TouchPhase::Moved, 104.0, 103.0), false);
+        assert_eq!(
+            gestures.handle(&touch(0, TouchPhase::Ended, 104.0, 103.0), false),
+            Some(Gesture::Tap(glm::vec2(104.0, 103.0)))
+        );
+    }
+
+    #[test]
+    fn drag_past_the_tap_distance_is_not_a_tap() {
+        let mut gestures = TouchGestures::default();
+        gestures.handle(&touch(0, TouchPhase::Started, 100.0, 100.0), false);
+        let moved = 100.0 + TouchGestures::TAP_DISTANCE as f64;
+        assert_eq!(
+            gestures.handle(&touch(0, TouchPhase::Moved, moved, 100.0), false),
+            Some(Gesture::Rotate(glm::vec2(TouchGestures::TAP_DISTANCE, 0.0)))
+        );
+        assert_eq!(
+            gestures.handle(&touch(0, TouchPhase::Ended, moved, 100.0), false),
+            None
+        );
+    }
+
+    #[test]
+    fn pinch_is_the_ratio_of_the_distances() {
+        let mut gestures = TouchGestures::default();
+        gestures.handle(&touch(0, TouchPhase::Started, 100.0, 100.0), false);
+        gestures.handle(&touch(1, TouchPhase::Started, 200.0, 100.0), false);
+        let Some(Gesture::PanZoom { pan, pinch }) =
+            gestures.handle(&touch(1, TouchPhase::Moved, 300.0, 100.0), false)
+        else {
+            panic!("two fingers should pan and zoom");
+        };
+        assert_eq!(pan, glm::vec2(50.0, 0.0));
+        assert!((pinch - 2.0).abs() < 1e-6);
+        // Lifting the fingers after a pinch is no tap
+        assert_eq!(
+            gestures.handle(&touch(1, TouchPhase::Ended, 300.0, 100.0), false),
+            None
+        );
+        assert_eq!(
+            gestures.handle(&touch(0, TouchPhase::Ended, 100.0, 100.0), false),
+            None
+        );
+    }
+
+    #[test]
+    fn third_finger_is_ignored() {
+        let mut gestures = TouchGestures::default();
+        gestures.handle(&touch(0, TouchPhase::Started, 100.0, 100.0), false);
+        gestures.handle(&touch(1, TouchPhase::Started, 200.0, 100.0), false);
+        gestures.handle(&touch(2, TouchPhase::Started, 300.0, 100.0), false);
+        assert_eq!(
+            gestures.handle(&touch(2, TouchPhase::Moved, 400.0, 200.0), false),
+            None
+        );
+        // The first two fingers still pinch from where they are
+        let Some(Gesture::PanZoom { pinch, .. }) =
+            gestures.handle(&touch(1, TouchPhase::Moved, 300.0, 100.0), false)
+        else {
+            panic!("the first two fingers should pan and zoom");
+        };
+        assert!((pinch - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn touch_over_the_ui_is_left_out() {
+        let mut gestures = TouchGestures::default();
+        gestures.handle(&touch(0, TouchPhase::Started, 100.0, 100.0), true);
+        assert_eq!(
+            gestures.handle(&touch(0, TouchPhase::Moved, 150.0, 100.0), false),
+            None
+        );
+        assert_eq!(
+            gestures.handle(&touch(0, TouchPhase::Ended, 150.0, 100.0), false),
+            None
+        );
+    }
+}
@@ -0,0 +1,119 @@
+// Adaptive sampling, appended to raytracing.wgsl when the pipelines are created.
+//
+// The error of each pixel mean is estimated from the luminance of its samples. Before
+// tracing, `cs_adaptive` decides how many samples each pixel takes in the frame: none
+// once the pixel and its neighbours are under the noise threshold, up to
+// `ADAPTIVE_MAX_BOOST` times `samples_per_pixel` for the noisiest ones. The pixels still
+// above the threshold are counted whether adaptive sampling is enabled or not.
+
+// Must match `RenderParam::ADAPTIVE_MAX_BOOST`
+const ADAPTIVE_MAX_BOOST = 4u;
+// Keeps the relative error of dark pixels from exploding
+const ADAPTIVE_LUMINANCE_FLOOR = 0.01f;
+// Error of a pixel without enough samples to estimate it
+const ADAPTIVE_UNKNOWN_ERROR = 3.4e38f;
+
+struct PixelStats {
+    // Raw luminance of the samples, before outliers are rejected, so that the mean and
+    // the variance describe the same samples
+    luminance_sum: f32,
+    luminance_sq_sum: f32,
+    samples: u32,
+    // Samples to take in the current frame, written by `cs_adaptive`
+    frame_samples: u32,
+};
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+fn load_pixel(i: u32) -> vec3<f32> {
+    return vec3(image_buffer[i][0], image_buffer[i][1], image_buffer[i][2]);
+}
+
+fn store_pixel(i: u32, sum: vec3<f32>, stats: PixelStats) {
+    image_buffer[i] = array<f32, 3>(sum.r, sum.g, sum.b);
+    pixel_stats[i] = stats;
+}
+
+fn record_sample(stats: ptr<function, PixelStats>, color: vec3<f32>) {
+    let l = luminance(color);
+    (*stats).luminance_sum += l;
+    (*stats).luminance_sq_sum += l * l;
+    (*stats).samples += 1u;
+}
+
+// Standard error of the mean luminance of pixel `i` relative to the mean itself
+// The fields are read one by one, `frame_samples` of the neighbours is being written.
+fn relative_error(i: u32) -> f32 {
+    let samples = pixel_stats[i].samples;
+    if samples < max(render_param.adaptive_min_samples, 2u) {
+        return ADAPTIVE_UNKNOWN_ERROR;
+    }
+    let n = f32(samples);
+    let mean = pixel_stats[i].luminance_sum / n;
+    let variance = max(pixel_stats[i].luminance_sq_sum / n - mean * mean, 0.0) * n / (n - 1.0);
+    return sqrt(variance / n) / max(mean, ADAPTIVE_LUMINANCE_FLOOR);
+}
+
+// The worst error of the 3x3 neighbourhood, a pixel whose first samples all missed
+// the lights looks converged on its own.
+fn neighbourhood_error(pixel: vec2<u32>) -> f32 {
+    var error = 0.0;
+    for (var dy = -1; dy <= 1; dy += 1) {
+        for (var dx = -1; dx <= 1; dx += 1) {
+            let p = vec2<i32>(pixel) + vec2(dx, dy);
+            if p.x < 0 || p.y < 0 || p.x >= i32(frame_data.width) || p.y >= i32(frame_data.height) {
+                continue;
+            }
+            error = max(error, relative_error(u32(p.y) * frame_data.width + u32(p.x)));
+        }
+    }
+    return error;
+}
+
+var<workgroup> workgroup_noisy_pixels: atomic<u32>;
+
+@compute @workgroup_size(8, 8)
+fn cs_adaptive(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    if id.x < frame_data.width && id.y < frame_data.height {
+        if plan_pixel_samples(id.xy) {
+            atomicAdd(&workgroup_noisy_pixels, 1u);
+        }
+    }
+    workgroupBarrier();
+    if local_index == 0u {
+        atomicAdd(&noisy_pixels, atomicLoad(&workgroup_noisy_pixels));
+    }
+}
+
+// Writes the samples pixel `pixel` takes this frame, returns whether it is still noisy
+fn plan_pixel_samples(pixel: vec2<u32>) -> bool {
+    let i = pixel.y * frame_data.width + pixel.x;
+
+    if render_param.clear_samples == 1u {
+        let count = min(render_param.samples_per_pixel, render_param.samples_max_per_pixel);
+        store_pixel(i, vec3(0.0), PixelStats(0.0, 0.0, 0u, count));
+        return true;
+    }
+
+    let error = neighbourhood_error(pixel);
+
+    let samples = pixel_stats[i].samples;
+    let remaining = render_param.samples_max_per_pixel
+        - min(samples, render_param.samples_max_per_pixel);
+    var count = render_param.samples_per_pixel;
+    if render_param.adaptive != 0u {
+        if error <= render_param.noise_threshold {
+            count = 0u;
+        } else if error < ADAPTIVE_UNKNOWN_ERROR {
+            let boost = clamp(error / render_param.noise_threshold, 1.0, f32(ADAPTIVE_MAX_BOOST));
+            count *= u32(boost);
+        }
+    }
+    pixel_stats[i].frame_samples = min(count, remaining);
+    return error > render_param.noise_threshold;
+}
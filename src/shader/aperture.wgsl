@@ -0,0 +1,76 @@
+// Aperture shapes, appended to raytracing.wgsl when the pipelines are created.
+//
+// The lens is sampled over a circle, a regular polygon of `camera.aperture_blades`
+// sides, or the image of `aperture_mask` where it is bright. The shape is then rotated
+// and squeezed horizontally, out of focus highlights taking it on the image.
+
+// Offset on the lens of unit radius for the sample `u`
+fn sample_aperture(u: vec2<f32>) -> vec2<f32> {
+    var p: vec2<f32>;
+    if aperture_mask[0] > 0.0 {
+        p = sample_aperture_mask(u);
+    } else if camera.aperture_blades >= 3u {
+        p = square_to_polygon(u, camera.aperture_blades);
+    } else {
+        p = square_to_disk(u);
+    }
+
+    let c = cos(camera.aperture_rotation);
+    let s = sin(camera.aperture_rotation);
+    p = vec2(c * p.x - s * p.y, s * p.x + c * p.y);
+    return vec2(p.x / camera.aperture_squeeze, p.y);
+}
+
+// Uniform point on the regular polygon inscribed in the unit circle, a corner on top.
+// `u.x` picks the triangle between the center and one side, then places the point
+// between the center and that side.
+fn square_to_polygon(u: vec2<f32>, sides: u32) -> vec2<f32> {
+    let n = f32(sides);
+    let side = min(floor(u.x * n), n - 1.0);
+    let t = u.x * n - side;
+    let angle0 = FRAC_PI_2 + 2.0 * PI * side / n;
+    let angle1 = angle0 + 2.0 * PI / n;
+    let corner0 = vec2(cos(angle0), sin(angle0));
+    let corner1 = vec2(cos(angle1), sin(angle1));
+    return sqrt(t) * mix(corner0, corner1, u.y);
+}
+
+// Point on the square around the unit circle, distributed as the luminance of the mask
+fn sample_aperture_mask(u: vec2<f32>) -> vec2<f32> {
+    let width = u32(aperture_mask[0]);
+    let height = u32(aperture_mask[1]);
+    let rows = 2u;
+    let row = find_cdf_interval(rows, height, u.y);
+    let y = (f32(row) + cdf_interval_fraction(rows + row, u.y)) / f32(height);
+
+    let columns = rows + height + 1u + row * (width + 1u);
+    let column = find_cdf_interval(columns, width, u.x);
+    let x = (f32(column) + cdf_interval_fraction(columns + column, u.x)) / f32(width);
+    return 2.0 * vec2(x, y) - 1.0;
+}
+
+// Interval of the `count` ones of the cumulative distribution starting at `start` in
+// `aperture_mask` containing `u`, skipping the empty intervals
+fn find_cdf_interval(start: u32, count: u32, u: f32) -> u32 {
+    var low = 0u;
+    var high = count - 1u;
+    while low < high {
+        let middle = (low + high + 1u) / 2u;
+        if aperture_mask[start + middle] <= u {
+            low = middle;
+        } else {
+            high = middle - 1u;
+        }
+    }
+    return low;
+}
+
+// Where `u` falls in the interval of the cumulative distribution starting at `index`
+fn cdf_interval_fraction(index: u32, u: f32) -> f32 {
+    let low = aperture_mask[index];
+    let high = aperture_mask[index + 1u];
+    if high <= low {
+        return 0.5;
+    }
+    return clamp((u - low) / (high - low), 0.0, 1.0);
+}
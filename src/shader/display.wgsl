@@ -0,0 +1,113 @@
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coords: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+struct RenderParam {
+    samples_max_per_pixel: u32,
+    samples_per_pixel: u32,
+    total_samples: u32,
+    clear_samples: u32,
+    max_depth: u32,
+    rr_min_depth: u32,
+    sampler_kind: u32,
+    adaptive: u32,
+    noise_threshold: f32,
+    adaptive_min_samples: u32,
+    show_heatmap: u32,
+    filter_kind: u32,
+    filter_radius: f32,
+    seed: u32,
+    clamp_direct: f32,
+    clamp_indirect: f32,
+    outlier_sigma: f32,
+};
+
+struct PixelStats {
+    luminance_sum: f32,
+    luminance_sq_sum: f32,
+    samples: u32,
+    frame_samples: u32,
+};
+
+struct Frame {
+    width: u32,
+    height: u32,
+    frame_idx: u32,
+};
+
+@group(0) @binding(1) var<uniform> frame_data: Frame;
+@group(0) @binding(2) var<uniform> render_param: RenderParam;
+@group(0) @binding(3) var<storage, read> image_buffer: array<array<f32, 3>>;
+@group(0) @binding(4) var<storage, read> pixel_stats: array<PixelStats>;
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    return VertexOutput(
+        vec4<f32>(model.position, 0.0, 1.0),
+        model.tex_coords,
+    );
+}
+
+fn apply_transfer_function(x: f32) -> u32 {
+    let a = 0.055;
+    var y: f32;
+    if x > 0.0031308 {
+        y = (1.0 + a) * pow(x, 1.0 / 2.4) - a;
+    } else {
+        y = 12.92 * x;
+    }
+    return u32(round(y * 255.0));
+}
+
+fn from_linear_rgb(c: vec3<f32>) -> vec3<f32> {
+
+    let r = apply_transfer_function(c.x);
+    let g = apply_transfer_function(c.y);
+    let b = apply_transfer_function(c.z);
+
+    return vec3<f32>(f32(r), f32(g), f32(b)) / 255.0;
+}
+
+fn pixel_index(tex_coords: vec2<f32>) -> u32 {
+    let x = min(u32(tex_coords.x * f32(frame_data.width)), frame_data.width - 1u);
+    let y = min(u32(tex_coords.y * f32(frame_data.height)), frame_data.height - 1u);
+    return y * frame_data.width + x;
+}
+
+// Blue for few samples to red for `samples_max_per_pixel`
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0);
+    return clamp(vec3(1.5) - abs(4.0 * x - vec3(3.0, 2.0, 1.0)), vec3(0.0), vec3(1.0));
+}
+
+// Average of the samples accumulated by the path tracer for this fragment, or the
+// number of samples of the pixel when the heatmap is shown
+fn accumulated_color(tex_coords: vec2<f32>) -> vec3<f32> {
+    let i = pixel_index(tex_coords);
+    let samples = pixel_stats[i].samples;
+    if render_param.show_heatmap != 0u {
+        return heatmap(f32(samples) / f32(max(render_param.samples_max_per_pixel, 1u)));
+    }
+
+    let pixel = vec3(image_buffer[i][0], image_buffer[i][1], image_buffer[i][2]);
+    return pixel / f32(max(samples, 1u));
+}
+
+// for webgpu
+@fragment
+fn fs_main_rgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(from_linear_rgb(accumulated_color(in.tex_coords)), 1.0);
+}
+
+@fragment
+fn fs_main_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(accumulated_color(in.tex_coords), 1.0);
+}
@@ -0,0 +1,51 @@
+// Pixel reconstruction filters, appended to raytracing.wgsl when the pipelines are created.
+//
+// The offset of the camera ray from the pixel center is drawn from the separable filter
+// through the inverse CDF tables built by `GpuFilterTable`. Samples landing in a
+// negative lobe carry a negative weight, the image stays a plain average of the samples.
+
+const FILTER_BOX = 0u;
+const FILTER_KINDS = 5u;
+// Must match `FILTER_BINS` in filter.rs
+const FILTER_BINS = 64u;
+
+struct FilterTable {
+    // Two (offset, weight) bins per vec4
+    bins: array<array<vec4<f32>, 32>, FILTER_KINDS>,
+};
+
+struct FilterSample {
+    // From the pixel center, in pixels
+    offset: vec2<f32>,
+    weight: f32,
+};
+
+fn filter_bin(kind: u32, bin: u32) -> vec2<f32> {
+    let packed = filter_table.bins[kind][bin / 2u];
+    if (bin & 1u) == 0u {
+        return packed.xy;
+    }
+    return packed.zw;
+}
+
+// Maps `u` in [0, 1) to an offset in [-1, 1] and its weight
+fn sample_filter_1d(kind: u32, u: f32) -> vec2<f32> {
+    let t = u * f32(FILTER_BINS);
+    let bin = min(u32(t), FILTER_BINS - 1u);
+    let lower = filter_bin(kind, bin);
+    var upper = 1.0;
+    if bin + 1u < FILTER_BINS {
+        upper = filter_bin(kind, bin + 1u).x;
+    }
+    return vec2(mix(lower.x, upper, t - f32(bin)), lower.y);
+}
+
+fn sample_filter(u: vec2<f32>) -> FilterSample {
+    let kind = min(render_param.filter_kind, FILTER_KINDS - 1u);
+    if kind == FILTER_BOX {
+        return FilterSample((2.0 * u - 1.0) * render_param.filter_radius, 1.0);
+    }
+    let x = sample_filter_1d(kind, u.x);
+    let y = sample_filter_1d(kind, u.y);
+    return FilterSample(vec2(x.x, y.x) * render_param.filter_radius, x.y * y.y);
+}
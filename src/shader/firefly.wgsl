@@ -0,0 +1,40 @@
+// Firefly suppression, appended to raytracing.wgsl when the pipelines are created.
+//
+// Light reaching the camera after one bounce is direct, after more bounces indirect.
+// Their contributions can be scaled down to a maximum luminance, and samples far
+// above the mean of their pixel can be rejected. Both bias the image towards darker
+// but much less noisy highlights, a zero in `RenderParam` disables each of them.
+
+// Scales `contribution` of light reached after `bounces` scattering events down to the
+// clamp luminance of its kind, the light seen directly by the camera is never clamped.
+fn clamp_contribution(contribution: vec3<f32>, bounces: u32) -> vec3<f32> {
+    if bounces == 0u {
+        return contribution;
+    }
+    if bounces == 1u {
+        return clamp_luminance(contribution, render_param.clamp_direct);
+    }
+    return clamp_luminance(contribution, render_param.clamp_indirect);
+}
+
+fn clamp_luminance(color: vec3<f32>, max_luminance: f32) -> vec3<f32> {
+    let l = luminance(color);
+    if max_luminance <= 0.0 || l <= max_luminance {
+        return color;
+    }
+    return color * (max_luminance / l);
+}
+
+// Scales `sample` down to `outlier_sigma` standard deviations above the mean luminance
+// of the pixel, `stats` describing the raw samples recorded before it.
+fn reject_outlier(sample: vec3<f32>, stats: PixelStats) -> vec3<f32> {
+    // The spread of the pixel is not trusted before the adaptive sampling trusts it
+    if render_param.outlier_sigma <= 0.0
+        || stats.samples < max(render_param.adaptive_min_samples, 2u) {
+        return sample;
+    }
+    let n = f32(stats.samples);
+    let mean = stats.luminance_sum / n;
+    let variance = max(stats.luminance_sq_sum / n - mean * mean, 0.0);
+    return clamp_luminance(sample, mean + render_param.outlier_sigma * sqrt(variance));
+}
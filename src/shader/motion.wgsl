@@ -0,0 +1,77 @@
+// Motion blur, appended to raytracing.wgsl when the pipelines are created.
+//
+// Each camera ray gets a time in the shutter interval of the camera, which the rays
+// scattered along its path keep. Spheres move from `center0` to `center1` and objects
+// from the first to the second transform of their motion as the time goes from 0 to 1.
+
+// Rigid transform, a rotation about the origin followed by a translation.
+// Matches `Transform` in motion.rs.
+struct Transform {
+    translation: vec4<f32>,
+    // Unit quaternion, w last
+    rotation: vec4<f32>,
+}
+
+fn sample_time(rngState: ptr<function, u32>) -> f32 {
+    return mix(camera.shutter_open, camera.shutter_close, sample_1d(rngState, DIM_TIME));
+}
+
+fn sphere_center(sphere: Sphere, time: f32) -> vec3<f32> {
+    return mix(sphere.center0.xyz, sphere.center1.xyz, time);
+}
+
+fn interpolate_transform(start: Transform, end: Transform, time: f32) -> Transform {
+    return Transform(
+        mix(start.translation, end.translation, time),
+        quat_slerp(start.rotation, end.rotation, time)
+    );
+}
+
+fn quat_slerp(a: vec4<f32>, b: vec4<f32>, t: f32) -> vec4<f32> {
+    // Along the shortest arc
+    var end = b;
+    var cos_theta = dot(a, b);
+    if cos_theta < 0.0 {
+        end = -b;
+        cos_theta = -cos_theta;
+    }
+    if cos_theta > 1.0 - EPSILON {
+        return normalize(mix(a, end, t));
+    }
+    let theta = acos(cos_theta);
+    return (sin((1.0 - t) * theta) * a + sin(t * theta) * end) / sin(theta);
+}
+
+fn quat_rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+fn transform_point(transform: Transform, p: vec3<f32>) -> vec3<f32> {
+    return quat_rotate(transform.rotation, p) + transform.translation.xyz;
+}
+
+fn transform_vector(transform: Transform, v: vec3<f32>) -> vec3<f32> {
+    return quat_rotate(transform.rotation, v);
+}
+
+fn inverse_transform_ray(transform: Transform, ray: Ray) -> Ray {
+    let inverse = vec4(-transform.rotation.xyz, transform.rotation.w);
+    return Ray(
+        quat_rotate(inverse, ray.origin - transform.translation.xyz),
+        quat_rotate(inverse, ray.direction),
+        ray.time
+    );
+}
+
+fn object_transform(object: Object, time: f32) -> Transform {
+    return interpolate_transform(object.motion[0], object.motion[1], time);
+}
+
+// Camera ray at rest moved with the camera at its time, rotating about the eye
+fn move_camera_ray(ray: Ray) -> Ray {
+    let identity = Transform(vec4(0.0), vec4(0.0, 0.0, 0.0, 1.0));
+    let motion = interpolate_transform(identity, camera.motion, ray.time);
+    let origin = camera.eye + transform_vector(motion, ray.origin - camera.eye) + motion.translation.xyz;
+    return Ray(origin, transform_vector(motion, ray.direction), ray.time);
+}
@@ -1,13 +1,3 @@
-struct VertexInput {
-    @location(0) position: vec2<f32>,
-    @location(1) tex_coords: vec2<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>,
-};
-
 const EPSILON = 0.0001f;
 const PI = 3.1415927f;
 const FRAC_1_PI = 0.31830987f;
@@ -16,116 +6,46 @@ const FRAC_PI_2 = 1.5707964f;
 const MIN_T = 0.001f;
 const MAX_T = 1000f;
 
+// Must match `Projection::id`
+const PROJECTION_PERSPECTIVE = 0u;
+const PROJECTION_ORTHOGRAPHIC = 1u;
+const PROJECTION_FISHEYE = 2u;
+const PROJECTION_EQUIRECTANGULAR = 3u;
+const PROJECTION_ODS = 4u;
+
 @group(0) @binding(0) var<uniform> camera: Camera;
 @group(0) @binding(1) var<uniform> frame_data: Frame;
 @group(0) @binding(2) var<uniform> render_param: RenderParam;
 @group(0) @binding(3) var<storage, read_write> image_buffer: array<array<f32, 3>>;
+@group(0) @binding(4) var<storage, read_write> pixel_stats: array<PixelStats>;
+@group(0) @binding(5) var<uniform> filter_table: FilterTable;
+// Pixels above the noise threshold, counted by `cs_adaptive` for the noise stop criterion
+@group(0) @binding(6) var<storage, read_write> noisy_pixels: atomic<u32>;
 
 @group(1) @binding(0) var<storage, read> objects: array<Object>;
 @group(1) @binding(1) var<storage, read> spheres: array<Sphere>;
 @group(1) @binding(2) var<storage, read> materials: array<Material>;
 @group(1) @binding(3) var<storage, read> textures: array<array<f32, 3>>;
 @group(1) @binding(4) var<storage, read> surfaces: array<Surface>;
+// See `Scene::gpu_aperture_mask`
+@group(1) @binding(5) var<storage, read> aperture_mask: array<f32>;
 
 
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    return VertexOutput(
-        vec4<f32>(model.position, 0.0, 1.0),
-        model.tex_coords,
-    );
-}
-
-fn apply_transfer_function(x: f32) -> u32 {
-    let a = 0.055;
-    var y: f32;
-    if x > 0.0031308 {
-        y = (1.0 + a) * pow(x, 1.0 / 2.4) - a;
-    } else {
-        y = 12.92 * x;
+@compute @workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let x = id.x;
+    let y = id.y;
+    if x >= frame_data.width || y >= frame_data.height {
+        return;
     }
-    return u32(round(y * 255.0));
-}
-fn from_linear_rgb(c: vec3<f32>) -> vec3<f32> {
-
-    let r = apply_transfer_function(c.x);
-    let g = apply_transfer_function(c.y);
-    let b = apply_transfer_function(c.z);
-
-    return vec3<f32>(f32(r), f32(g), f32(b)) / 255.0;
-}
-
-// for webgpu
-@fragment
-fn fs_main_rgb(in: VertexOutput) -> @location(0) vec4<f32> {
-    let u = in.tex_coords.x;
-    let v = in.tex_coords.y;
-
-    let x = u32(u * f32(frame_data.width));
-    let y = u32(v * f32(frame_data.height));
-    let i = y * frame_data.width + x;
-
-    var rngState: u32 = init_rng(
-        vec2<u32>(u32(x), u32(y)),
-        vec2<u32>(frame_data.width, frame_data.height),
-        frame_data.frame_idx
-    );
-
-    var pixel = vec3(image_buffer[i][0], image_buffer[i][1], image_buffer[i][2]);
-
-    if render_param.clear_samples == 1u {
-        pixel = vec3(0.0);
-    }
-
-    var rgb = sample_pixel(&rngState, f32(x), f32(y));
-    rgb = from_linear_rgb(rgb);
-
-    pixel += rgb;
-    image_buffer[i] = array<f32, 3>(pixel.r, pixel.g, pixel.b);
-
-    return vec4<f32>(
-        pixel / f32(render_param.total_samples),
-        1.0
-    );
-
-    // var noiseState: u32 = init_rng(vec2<u32>(u32(u), u32(v)), vec2<u32>(512u, 512u), 0u);
-    // return vec4<f32>(rng_next_float(&rngState), rng_next_float(&rngState), rng_next_float(&rngState), 1.0);
-}
-
-@fragment
-fn fs_main_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
-    let u = in.tex_coords.x;
-    let v = in.tex_coords.y;
-
-    let x = u32(u * f32(frame_data.width));
-    let y = u32(v * f32(frame_data.height));
     let i = y * frame_data.width + x;
 
-    var rngState: u32 = init_rng(
-        vec2<u32>(u32(x), u32(y)),
-        vec2<u32>(frame_data.width, frame_data.height),
-        frame_data.frame_idx
-    );
-
-    var pixel = vec3(image_buffer[i][0], image_buffer[i][1], image_buffer[i][2]);
-
-    if render_param.clear_samples == 1u {
-        pixel = vec3(0.0);
+    var stats = pixel_stats[i];
+    if stats.frame_samples == 0u {
+        return;
     }
-
-    let rgb = sample_pixel(&rngState, f32(x), f32(y));
-    pixel += rgb;
-    image_buffer[i] = array<f32, 3>(pixel.r, pixel.g, pixel.b);
-
-    return vec4<f32>(
-        pixel / f32(render_param.total_samples),
-        1.0
-    );
-
-    // var noiseState: u32 = init_rng(vec2<u32>(u32(u), u32(v)), vec2<u32>(512u, 512u), 0u);
-    // return vec4<f32>(rng_next_float(&rngState), rng_next_float(&rngState), rng_next_float(&rngState), 1.0);
+    let sum = load_pixel(i);
+    store_pixel(i, sum + sample_pixel(f32(x), f32(y), &stats), stats);
 }
 
 struct RenderParam {
@@ -134,6 +54,18 @@ struct RenderParam {
     total_samples: u32,
     clear_samples: u32,
     max_depth: u32,
+    rr_min_depth: u32,
+    sampler_kind: u32,
+    adaptive: u32,
+    noise_threshold: f32,
+    adaptive_min_samples: u32,
+    show_heatmap: u32,
+    filter_kind: u32,
+    filter_radius: f32,
+    seed: u32,
+    clamp_direct: f32,
+    clamp_indirect: f32,
+    outlier_sigma: f32,
 };
 
 struct Frame {
@@ -150,6 +82,21 @@ struct Camera {
     v: vec3<f32>,
     lensRadius: f32,
     lowerLeftCorner: vec3<f32>,
+    // Factor applied to the radiance of the samples
+    exposure: f32,
+    // Normalized view direction
+    w: vec3<f32>,
+    projection: u32,
+    fisheye_fov: f32,
+    ods_ipd: f32,
+    // Below 3 the aperture is circular
+    aperture_blades: u32,
+    aperture_rotation: f32,
+    aperture_squeeze: f32,
+    shutter_open: f32,
+    shutter_close: f32,
+    // Move of the camera from time 0 to 1, see `move_camera_ray`
+    motion: Transform,
 }
 
 struct Object {
@@ -157,6 +104,10 @@ struct Object {
     obj_type: u32,
     // for when object is has multiple meshes
     count: u32,
+    // Non zero when `motion` moves the object
+    moving: u32,
+    // Transforms of the geometry at time 0 and 1
+    motion: array<Transform, 2>,
 };
 
 const OBJECT_SPHERE = 0u;
@@ -165,12 +116,15 @@ const OBJECT_MESHES = 1u;
 struct Ray {
     origin: vec3<f32>,
     direction: vec3<f32>,
+    // In the frame, see `sample_time`
+    time: f32,
 };
 
 struct Sphere {
-    center: vec4<f32>,
+    center0: vec4<f32>,
     radius: f32,
     material_index: u32,
+    center1: vec4<f32>,
 };
 
 struct Surface {
@@ -217,8 +171,9 @@ fn hit_sphere(
     hit: ptr<function, HitRecord>,
 ) -> bool {
     let sphere = spheres[sphere_index];
+    let center = sphere_center(sphere, ray.time);
 
-    let oc = ray.origin - sphere.center.xyz;
+    let oc = ray.origin - center;
     let a = dot(ray.direction, ray.direction);
     let b = dot(ray.direction, oc);
     let c = dot(oc, oc) - sphere.radius * sphere.radius;
@@ -239,13 +194,13 @@ fn hit_sphere(
     }
 
 
-    *hit = sphereIntersection(ray, sphere, root, sphere_index);
+    *hit = sphereIntersection(ray, center, sphere.radius, root, sphere_index);
     return true;
 }
 
-fn sphereIntersection(ray: Ray, sphere: Sphere, t: f32, material_index: u32) -> HitRecord {
+fn sphereIntersection(ray: Ray, center: vec3<f32>, radius: f32, t: f32, material_index: u32) -> HitRecord {
     let p = ray.origin + t * ray.direction;
-    var normal = (p - sphere.center.xyz) / sphere.radius;
+    var normal = (p - center) / radius;
     var front_face = true;
     if dot(ray.direction, normal) > 0.0 {
         normal = -normal;
@@ -328,46 +283,151 @@ fn check_intersection(ray: Ray, intersection: ptr<function, HitRecord>) -> bool
     var mesh_offset = 0u;
     for (var i = 0u; i < arrayLength(&objects); i += 1u) {
         let obj = objects[i];
+        // Moving objects are hit in the space of their geometry, the transforms are
+        // rigid so that the distances along the ray stay the same
+        var object_ray = ray;
+        var transform: Transform;
+        if obj.moving != 0u {
+            transform = object_transform(obj, ray.time);
+            object_ray = inverse_transform_ray(transform, ray);
+        }
+        var hit_object_anything = false;
         if obj.count > 1u {
             for (var j = 0u; j < obj.count; j += 1u) {
-                if hit_triangle(mesh_offset + i + j, obj.id, ray, MIN_T, closest_so_far, &tmp_rec) {
-                    hit_anything = true;
+                if hit_triangle(mesh_offset + i + j, obj.id, object_ray, MIN_T, closest_so_far, &tmp_rec) {
+                    hit_object_anything = true;
                     closest_so_far = tmp_rec.t;
                     *intersection = tmp_rec;
                 }
             }
             mesh_offset += obj.count - 1u;
         } else {
-            if hit_object(i, ray, MIN_T, closest_so_far, &tmp_rec) {
-                hit_anything = true;
+            if hit_object(i, object_ray, MIN_T, closest_so_far, &tmp_rec) {
+                hit_object_anything = true;
                 closest_so_far = tmp_rec.t;
                 *intersection = tmp_rec;
             }
         }
+        if hit_object_anything && obj.moving != 0u {
+            (*intersection).p = ray.origin + (*intersection).t * ray.direction;
+            (*intersection).normal = transform_vector(transform, (*intersection).normal);
+        }
+        hit_anything = hit_anything || hit_object_anything;
     }
 
     return hit_anything;
 }
 
-fn sample_pixel(rngState: ptr<function, u32>, x: f32, y: f32) -> vec3<f32> {
+// Traces the samples of the frame for the pixel, `stats` recording them
+fn sample_pixel(x: f32, y: f32, stats: ptr<function, PixelStats>) -> vec3<f32> {
+    let pixel = vec2(u32(x), u32(y));
     var color = vec3(0.0);
-    for (var i = 0u; i < render_param.samples_per_pixel; i += 1u) {
-        let ray = get_ray(rngState, x, y);
-        color += ray_color(ray, rngState);
+    for (var i = 0u; i < (*stats).frame_samples; i += 1u) {
+        var rngState = init_rng(
+            pixel,
+            vec2<u32>(frame_data.width, frame_data.height),
+            (*stats).samples,
+            render_param.seed
+        );
+        init_sampler(pixel, (*stats).samples);
+        let film_sample = sample_filter(sample_2d(&rngState, DIM_PIXEL));
+        let ray = get_ray(&rngState, vec2(x, y) + 0.5 + film_sample.offset);
+        var sample = vec3(0.0);
+        if is_camera_ray(ray) {
+            sample = camera.exposure * film_sample.weight * ray_color(ray, &rngState);
+        }
+        color += reject_outlier(sample, *stats);
+        // The statistics keep the raw sample, they would shrink with each rejection otherwise
+        record_sample(stats, sample);
     }
     return color;
 }
 
-fn get_ray(rngState: ptr<function, u32>, x: f32, y: f32) -> Ray {
-    let u = f32(x + rng_next_float(rngState)) / f32(frame_data.width);
-    let v = f32(y + rng_next_float(rngState)) / f32(frame_data.height);
+// Camera ray through `film`, a position on the image in pixels. Outside of the image
+// circle of the fisheye the direction is zero, see `is_camera_ray`.
+fn get_ray(rngState: ptr<function, u32>, film: vec2<f32>) -> Ray {
+    let u = film.x / f32(frame_data.width);
+    let v = film.y / f32(frame_data.height);
+    let time = sample_time(rngState);
+
+    var ray: Ray;
+    switch camera.projection {
+        case PROJECTION_ORTHOGRAPHIC: {
+            let origin = camera.lowerLeftCorner + u * camera.horizontal + v * camera.vertical;
+            ray = Ray(origin, camera.w, time);
+        }
+        case PROJECTION_FISHEYE: {
+            ray = Ray(camera.eye, fisheye_direction(film), time);
+        }
+        case PROJECTION_EQUIRECTANGULAR: {
+            ray = Ray(camera.eye, panorama_direction(vec2(u, v)), time);
+        }
+        case PROJECTION_ODS: {
+            ray = ods_ray(vec2(u, v), time);
+        }
+        default: {
+            let rd = camera.lensRadius * sample_aperture(sample_2d(rngState, DIM_LENS));
+
+            let origin = camera.eye + rd.x * camera.u + rd.y * camera.v;
+            let direction = camera.lowerLeftCorner + u * camera.horizontal + v * camera.vertical - origin;
+            ray = Ray(origin, direction, time);
+        }
+    }
+
+    return move_camera_ray(ray);
+}
+
+fn is_camera_ray(ray: Ray) -> bool {
+    return any(ray.direction != vec3(0.0));
+}
 
-    let rd = camera.lensRadius * rng_in_unit_disk(rngState);
+struct Basis {
+    right: vec3<f32>,
+    up: vec3<f32>,
+    forward: vec3<f32>,
+}
 
-    let origin = camera.eye + rd.x * camera.u + rd.y * camera.v;
-    let direction = camera.lowerLeftCorner + u * camera.horizontal + v * camera.vertical - origin;
+// Camera axes with a level forward, so that the horizon of the panoramas stays straight
+fn camera_level_basis() -> Basis {
+    let up = normalize(camera.v);
+    let right = normalize(cross(camera.w, up));
+    return Basis(right, up, cross(up, right));
+}
 
-    return Ray(origin, direction);
+// Equidistant fisheye over the largest circle fitting in the image
+fn fisheye_direction(film: vec2<f32>) -> vec3<f32> {
+    let size = vec2(f32(frame_data.width), f32(frame_data.height));
+    let p = (film - 0.5 * size) / (0.5 * min(size.x, size.y));
+    let r = length(p);
+    if r > 1.0 {
+        return vec3(0.0);
+    }
+    let theta = r * 0.5 * camera.fisheye_fov;
+    let phi = atan2(p.y, p.x);
+    let basis = camera_level_basis();
+    let tilted_up = cross(basis.right, camera.w);
+    return cos(theta) * camera.w + sin(theta) * (cos(phi) * basis.right + sin(phi) * tilted_up);
+}
+
+// Direction of `uv` on an equirectangular panorama, the forward axis at its center
+fn panorama_direction(uv: vec2<f32>) -> vec3<f32> {
+    let phi = (uv.x - 0.5) * 2.0 * PI;
+    let elevation = (uv.y - 0.5) * PI;
+    let basis = camera_level_basis();
+    let horizontal = sin(phi) * basis.right + cos(phi) * basis.forward;
+    return cos(elevation) * horizontal + sin(elevation) * basis.up;
+}
+
+// Omni-directional stereo, the eyes turn on a circle of diameter `ods_ipd` so that each
+// ray leaves from the eye seeing it straight ahead. Left eye on top, right eye below.
+fn ods_ray(uv: vec2<f32>, time: f32) -> Ray {
+    let left_eye = uv.y >= 0.5;
+    let eye_uv = vec2(uv.x, 2.0 * uv.y - select(0.0, 1.0, left_eye));
+    let phi = (eye_uv.x - 0.5) * 2.0 * PI;
+    let basis = camera_level_basis();
+    let side = cos(phi) * basis.right - sin(phi) * basis.forward;
+    let offset = select(0.5, -0.5, left_eye) * camera.ods_ipd;
+    return Ray(camera.eye + offset * side, panorama_direction(eye_uv), time);
 }
 
 
@@ -378,6 +438,7 @@ fn ray_color(first_ray: Ray, rngState: ptr<function, u32>) -> vec3<f32> {
     var color_from_emission = vec3(0.0);
 
     for (var i = 0u; i < render_param.max_depth; i += 1u) {
+        sampler_start_bounce(i);
         var intersection = HitRecord();
         if !check_intersection(ray, &intersection) {
             let direction = normalize(ray.direction);
@@ -393,7 +454,7 @@ fn ray_color(first_ray: Ray, rngState: ptr<function, u32>) -> vec3<f32> {
         let material = materials[intersection.material_index];
         if material.id == MAT_DIFFUSE_LIGHT {
             let emitted = texture_look_up(material.desc, 0.5, 0.5);
-            color_from_emission += color_from_scatter * emitted;
+            color_from_emission += clamp_contribution(color_from_scatter * emitted, i);
             break;
         }
         let scattered = scatter(ray, intersection, material, rngState);
@@ -403,10 +464,27 @@ fn ray_color(first_ray: Ray, rngState: ptr<function, u32>) -> vec3<f32> {
 
         color_from_scatter *= (scattered.attenuation * scattering_pdf) / pdf;
         ray = scattered.ray;
+
+        if i + 1u >= render_param.rr_min_depth {
+            if !russian_roulette(&color_from_scatter, rngState) {
+                break;
+            }
+        }
     }
     return color_from_emission + color_from_scatter * sky_color;
 }
 
+// Terminates the path with a probability based on its throughput, surviving paths are
+// reweighted so that the estimate stays unbiased.
+fn russian_roulette(throughput: ptr<function, vec3<f32>>, rngState: ptr<function, u32>) -> bool {
+    let p = min(max((*throughput).r, max((*throughput).g, (*throughput).b)), 1.0);
+    if sample_1d(rngState, DIM_ROULETTE) >= p {
+        return false;
+    }
+    *throughput /= p;
+    return true;
+}
+
 struct ONB {
     u: vec3<f32>,
     v: vec3<f32>,
@@ -436,10 +514,10 @@ fn scatter(
         case MAT_LAMBERTIAN: 
         {
             let onb = pixar_onb(hit.normal);
-            let cos_rnd = rng_in_cosine_hemisphere(rngState);
+            let cos_rnd = square_to_cosine_hemisphere(sample_2d(rngState, DIM_BSDF));
             let direction = onb.u * cos_rnd.x + onb.v * cos_rnd.y + onb.w * cos_rnd.z;
 
-            let scatter = Ray(hit.p, direction);
+            let scatter = Ray(hit.p, direction, ray.time);
             let attenuation = texture_look_up(material.desc, 0.5, 0.5);
             return Scatter(scatter, attenuation);
         }
@@ -447,8 +525,8 @@ fn scatter(
         {
             let reflected = reflect(normalize(ray.direction), hit.normal);
             let fuzz = material.fuzz;
-            let direction = reflected + fuzz * rng_in_unit_sphere(rngState);
-            return Scatter(Ray(hit.p, direction), texture_look_up(material.desc, 0.5, 0.5));
+            let direction = reflected + fuzz * square_to_sphere(sample_2d(rngState, DIM_BSDF));
+            return Scatter(Ray(hit.p, direction, ray.time), texture_look_up(material.desc, 0.5, 0.5));
         }
         case MAT_DIELECTRIC: 
         {
@@ -463,7 +541,7 @@ fn scatter(
             let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
 
             var direction = vec3(0.0);
-            let rnd_float = rng_next_float(rngState);
+            let rnd_float = sample_1d(rngState, DIM_BSDF);
             if ri * sin_theta > 1.0 || reflectance(cos_theta, ri) > rnd_float {
                 direction = reflect(unit_direction, hit.normal);
             } else {
@@ -471,10 +549,10 @@ fn scatter(
             }
 
 
-            return Scatter(Ray(hit.p, direction), vec3(1.0));
+            return Scatter(Ray(hit.p, direction, ray.time), vec3(1.0));
         }
         default: {
-            return Scatter(Ray(vec3(0.0), vec3(0.0)), vec3(0.0));
+            return Scatter(Ray(vec3(0.0), vec3(0.0), ray.time), vec3(0.0));
         }
     }
 }
@@ -502,81 +580,6 @@ fn reflectance(cosine: f32, ref_idx: f32) -> f32 {
 }
 
 
-fn jenkin_hash(input: u32) -> u32 {
-    var x = input;
-    x += x << 10u;
-    x ^= x >> 6u;
-    x += x << 3u;
-    x ^= x >> 11u;
-    x += x << 15u;
-    return x;
-}
-
-fn init_rng(pixel: vec2<u32>, resolution: vec2<u32>, frame: u32) -> u32 {
-    // Adapted from https://github.com/boksajak/referencePT
-    let seed = dot(pixel, vec2<u32>(1u, resolution.x)) ^ jenkin_hash(frame);
-    return jenkin_hash(seed);
-}
-
-
-fn rng_on_hemisphere(rngState: ptr<function, u32>, normal: vec3<f32>) -> vec3<f32> {
-    let on_unit_sphere = rng_in_unit_sphere(rngState);
-    if dot(on_unit_sphere, normal) > 0.0 {
-        return on_unit_sphere;
-    } else {
-        return -on_unit_sphere;
-    }
-}
-
-fn rng_in_cosine_hemisphere(rngState: ptr<function, u32>) -> vec3<f32> {
-    let r1 = rng_next_float(rngState);
-    let r2 = rng_next_float(rngState);
-    let z = sqrt(1.0 - r2);
-    let phi = 2.0 * PI * r1;
-    let x = cos(phi) * sqrt(r2);
-    let y = sin(phi) * sqrt(r2);
-    return vec3(x, y, z);
-}
-
-fn rng_in_unit_sphere(state: ptr<function, u32>) -> vec3<f32> {
-    // Generate three random numbers x,y,z using Gaussian distribution
-    var x = rng_next_float_gauss(state);
-    var y = rng_next_float_gauss(state);
-    var z = rng_next_float_gauss(state);
-
-    // Multiply each number by 1/sqrt(x²+y²+z²) (a.k.a. Normalise) .
-    // case x=y=z=0 ?
-
-    let length = sqrt(x * x + y * y + z * z);
-    return vec3(x, y, z) / length;
-}
-
-fn rng_in_unit_disk(state: ptr<function, u32>) -> vec2<f32> {
-    var x = rng_next_float(state);
-    var y = rng_next_float(state);
-    return vec2(2.0 * x - 1.0, 2.0 * y - 1.0);
-}
-
-fn rng_next_int(state: ptr<function, u32>) -> u32 {
-    // PCG random number generator
-    // Based on https://www.shadertoy.com/view/XlGcRh
-    let newState = *state * 747796405u + 2891336453u;
-    *state = newState;
-    let word = ((newState >> ((newState >> 28u) + 4u)) ^ newState) * 277803737u;
-    return (word >> 22u) ^ word;
-}
-
-fn rng_next_float_gauss(state: ptr<function, u32>) -> f32 {
-    let x1 = rng_next_float(state);
-    let x2 = rng_next_float(state);
-    return sqrt(-2.0 * log(x1)) * cos(2.0 * PI * x2);
-}
-
-fn rng_next_float(state: ptr<function, u32>) -> f32 {
-    let x = rng_next_int(state);
-    return f32(*state) / f32(0xffffffffu);
-}
-
 fn texture_look_up(desc: TextureDescriptor, x: f32, y: f32) -> vec3<f32> {
     var u = clamp(x, 0f, 1f);
     var v = 1f - clamp(y, 0f, 1f);
@@ -0,0 +1,90 @@
+// Random number generation and the mappings from the unit square to the shapes
+// sampled by the path tracer. This file is appended to raytracing.wgsl when the
+// pipelines are created, `utils::rng` mirrors it on the CPU.
+
+fn jenkin_hash(input: u32) -> u32 {
+    var x = input;
+    x += x << 10u;
+    x ^= x >> 6u;
+    x += x << 3u;
+    x ^= x >> 11u;
+    x += x << 15u;
+    return x;
+}
+
+// State for the sample number `sample` of `pixel`, it only depends on the render seed and
+// not on the frames the samples were spread over so that renders are reproducible.
+fn init_rng(pixel: vec2<u32>, resolution: vec2<u32>, sample: u32, seed: u32) -> u32 {
+    // Adapted from https://github.com/boksajak/referencePT
+    let pixel_seed = dot(pixel, vec2<u32>(1u, resolution.x)) ^ jenkin_hash(sample ^ jenkin_hash(seed));
+    return jenkin_hash(pixel_seed);
+}
+
+fn rng_next_int(state: ptr<function, u32>) -> u32 {
+    // PCG random number generator
+    // Based on https://www.shadertoy.com/view/XlGcRh
+    let newState = *state * 747796405u + 2891336453u;
+    *state = newState;
+    let word = ((newState >> ((newState >> 28u) + 4u)) ^ newState) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform float in [0, 1)
+fn rng_next_float(state: ptr<function, u32>) -> f32 {
+    return u32_to_unit_float(rng_next_int(state));
+}
+
+fn u32_to_unit_float(x: u32) -> f32 {
+    // Keep 24 bits so that the result stays below 1
+    return f32(x >> 8u) * (1.0 / 16777216.0);
+}
+
+fn rng_next_vec2(state: ptr<function, u32>) -> vec2<f32> {
+    let x = rng_next_float(state);
+    return vec2(x, rng_next_float(state));
+}
+
+// Concentric mapping of the unit square to the unit disk, keeps the stratification.
+fn square_to_disk(u: vec2<f32>) -> vec2<f32> {
+    let offset = 2.0 * u - 1.0;
+    if offset.x == 0.0 && offset.y == 0.0 {
+        return vec2(0.0);
+    }
+    if abs(offset.x) > abs(offset.y) {
+        let theta = 0.25 * PI * (offset.y / offset.x);
+        return offset.x * vec2(cos(theta), sin(theta));
+    }
+    let theta = FRAC_PI_2 - 0.25 * PI * (offset.x / offset.y);
+    return offset.y * vec2(cos(theta), sin(theta));
+}
+
+// Uniform direction, i.e. a point on the unit sphere
+fn square_to_sphere(u: vec2<f32>) -> vec3<f32> {
+    let z = 1.0 - 2.0 * u.y;
+    let r = sqrt(max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * u.x;
+    return vec3(r * cos(phi), r * sin(phi), z);
+}
+
+// Uniform direction around +z
+fn square_to_hemisphere(u: vec2<f32>) -> vec3<f32> {
+    let z = u.y;
+    let r = sqrt(max(0.0, 1.0 - z * z));
+    let phi = 2.0 * PI * u.x;
+    return vec3(r * cos(phi), r * sin(phi), z);
+}
+
+// Cosine weighted direction around +z
+fn square_to_cosine_hemisphere(u: vec2<f32>) -> vec3<f32> {
+    let z = sqrt(1.0 - u.y);
+    let phi = 2.0 * PI * u.x;
+    let r = sqrt(u.y);
+    return vec3(cos(phi) * r, sin(phi) * r, z);
+}
+
+// Uniform barycentric coordinates (b1, b2) of a point in a triangle, the point being
+// v0 + b1 * (v1 - v0) + b2 * (v2 - v0).
+fn square_to_triangle(u: vec2<f32>) -> vec2<f32> {
+    let su = sqrt(u.x);
+    return vec2(su * (1.0 - u.y), su * u.y);
+}
@@ -0,0 +1,132 @@
+// Sample generation for the pixel, lens, BSDF and light dimensions of a path.
+// This file is appended to raytracing.wgsl when the pipelines are created.
+//
+// Every sample asks for a 2D point of a given dimension. The independent sampler
+// draws from the PCG state, the other ones use sequences indexed by the sample
+// number of the pixel so that consecutive samples stratify each other.
+
+const SAMPLER_INDEPENDENT = 0u;
+const SAMPLER_SOBOL = 1u;
+const SAMPLER_OWEN_SOBOL = 2u;
+const SAMPLER_BLUE_NOISE = 3u;
+
+// Dimensions used by the camera ray, then `DIMS_PER_BOUNCE` for each bounce
+const DIM_PIXEL = 0u;
+const DIM_LENS = 1u;
+const DIM_TIME = 2u;
+const DIMS_CAMERA = 3u;
+
+const DIM_LIGHT_PICK = 0u;
+const DIM_LIGHT_POINT = 1u;
+const DIM_BSDF = 2u;
+const DIM_ROULETTE = 3u;
+const DIMS_PER_BOUNCE = 4u;
+
+// 2^32 / golden ratio, generator of a Fibonacci lattice
+const FIBONACCI_GENERATOR = 2654435769u;
+// 2^32 * (1 / g, 1 / g^2) with g the plastic number, for the R2 pixel dither
+const R2_GENERATOR = vec2<u32>(3242174889u, 2447445414u);
+
+struct SamplerState {
+    pixel: vec2<u32>,
+    pixel_seed: u32,
+    // Sample number of the pixel since the accumulation started
+    index: u32,
+    // First dimension of the current bounce
+    dimension: u32,
+};
+
+var<private> sampler_state: SamplerState;
+
+fn init_sampler(pixel: vec2<u32>, index: u32) {
+    sampler_state.pixel = pixel;
+    // The render seed decorrelates the scrambling of renders made with different seeds
+    let seed = jenkin_hash(render_param.seed);
+    sampler_state.pixel_seed = jenkin_hash(pixel.x ^ jenkin_hash(pixel.y ^ seed));
+    sampler_state.index = index;
+    sampler_state.dimension = 0u;
+}
+
+fn sampler_start_bounce(depth: u32) {
+    sampler_state.dimension = DIMS_CAMERA + depth * DIMS_PER_BOUNCE;
+}
+
+fn sample_1d(rngState: ptr<function, u32>, dimension: u32) -> f32 {
+    if render_param.sampler_kind == SAMPLER_INDEPENDENT {
+        return rng_next_float(rngState);
+    }
+    return sample_2d(rngState, dimension).x;
+}
+
+fn sample_2d(rngState: ptr<function, u32>, dimension: u32) -> vec2<f32> {
+    if render_param.sampler_kind == SAMPLER_INDEPENDENT {
+        return rng_next_vec2(rngState);
+    }
+
+    let d = sampler_state.dimension + dimension;
+    var p: vec2<u32>;
+    if render_param.sampler_kind == SAMPLER_BLUE_NOISE {
+        p = blue_noise_rank1(sampler_state.index, d, sampler_state.pixel);
+    } else {
+        p = scrambled_sobol(sampler_state.index, hash_combine(sampler_state.pixel_seed, d));
+    }
+    return vec2(u32_to_unit_float(p.x), u32_to_unit_float(p.y));
+}
+
+fn hash_combine(seed: u32, v: u32) -> u32 {
+    return seed ^ (v + 0x9e3779b9u + (seed << 6u) + (seed >> 2u));
+}
+
+// The first two dimensions of the Sobol sequence, a (0, 2)-sequence in base 2.
+fn sobol_2d(index: u32) -> vec2<u32> {
+    var y = 0u;
+    var v = 0x80000000u;
+    for (var i = index; i != 0u; i >>= 1u) {
+        if (i & 1u) != 0u {
+            y ^= v;
+        }
+        v ^= v >> 1u;
+    }
+    return vec2(reverseBits(index), y);
+}
+
+// Hash based Owen scrambling, from "Practical Hash-based Owen Scrambling", Burley 2020.
+fn laine_karras_permutation(input: u32, seed: u32) -> u32 {
+    var x = input + seed;
+    x ^= x * 0x6c50b47cu;
+    x ^= x * 0xb82f1e52u;
+    x ^= x * 0xc7afe638u;
+    x ^= x * 0x8d22f6e6u;
+    return x;
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    return reverseBits(laine_karras_permutation(reverseBits(x), seed));
+}
+
+// Shuffles the sequence per dimension so that the dimensions are decorrelated, then
+// either Owen scrambles or XOR scrambles the point.
+fn scrambled_sobol(index: u32, seed: u32) -> vec2<u32> {
+    let p = sobol_2d(nested_uniform_scramble(index, seed));
+    if render_param.sampler_kind == SAMPLER_OWEN_SOBOL {
+        return vec2(
+            nested_uniform_scramble(p.x, hash_combine(seed, 1u)),
+            nested_uniform_scramble(p.y, hash_combine(seed, 2u))
+        );
+    }
+    return p ^ vec2(jenkin_hash(hash_combine(seed, 1u)), jenkin_hash(hash_combine(seed, 2u)));
+}
+
+// Extensible rank-1 lattice: the radical inverse of the index times an odd generator
+// per dimension, every power of two prefix is a Fibonacci lattice. The lattice is
+// shifted per pixel by an R2 dither, which spreads the error as blue noise on screen.
+fn blue_noise_rank1(index: u32, dimension: u32, pixel: vec2<u32>) -> vec2<u32> {
+    let generator = jenkin_hash(dimension + 1u) | 1u;
+    let lattice = reverseBits(index) * vec2(generator, generator * FIBONACCI_GENERATOR);
+    let dither = vec2(
+        dot(pixel, R2_GENERATOR),
+        dot(pixel.yx, R2_GENERATOR)
+    );
+    let shift = vec2(jenkin_hash(2u * dimension), jenkin_hash(2u * dimension + 1u));
+    return lattice + dither + shift;
+}
@@ -0,0 +1,391 @@
+// Wavefront path tracing kernels.
+// This file is appended to raytracing.wgsl when the pipelines are created, the
+// intersection, scattering and random number code is shared with the megakernel.
+//
+// Each bounce is split into kernels communicating through queues of path indices:
+// intersect -> shade (one kernel per material type) -> shadow rays -> next bounce.
+// Queue lengths are kept in atomic counters, `wf_dispatch_args` turns them into
+// indirect dispatch arguments between the stages.
+
+const WF_WORKGROUP_SIZE = 64u;
+
+const QUEUE_LAMBERTIAN = 0u;
+const QUEUE_METAL = 1u;
+const QUEUE_DIELECTRIC = 2u;
+
+// Emission hit by the path is added to its radiance, cleared once the light
+// was already sampled explicitly at the previous vertex.
+const PATH_COUNT_EMITTED = 1u;
+// A sample is traced, unset once the pixel took all its samples for the frame.
+const PATH_ACTIVE = 2u;
+
+const SHADOW_EPSILON = 0.001f;
+
+struct PathState {
+    origin: vec3<f32>,
+    rng: u32,
+    direction: vec3<f32>,
+    depth: u32,
+    throughput: vec3<f32>,
+    flags: u32,
+    radiance: vec3<f32>,
+    frame: u32,
+    hit_p: vec3<f32>,
+    hit_t: f32,
+    hit_normal: vec3<f32>,
+    hit_material: u32,
+    shadow_direction: vec3<f32>,
+    shadow_distance: f32,
+    shadow_contribution: vec3<f32>,
+    hit_front_face: u32,
+    // Sample number of the pixel since the accumulation started
+    sample_index: u32,
+    // Samples already traced for the pixel in this frame
+    frame_sample: u32,
+    // Reconstruction filter weight of the sample, applied to the radiance when accumulating
+    filter_weight: f32,
+    // Of the camera ray, kept by the rays scattered along the path
+    time: f32,
+};
+
+struct Counters {
+    rays: atomic<u32>,
+    next_rays: atomic<u32>,
+    shadow_rays: atomic<u32>,
+    // Which half of the ray queue holds the rays of the current bounce
+    parity: u32,
+    materials: array<atomic<u32>, 3>,
+};
+
+struct Light {
+    // OBJECT_SPHERE or OBJECT_MESHES
+    kind: u32,
+    // Index in spheres or surfaces
+    index: u32,
+    material_index: u32,
+    // A zero area marks an empty light list
+    area: f32,
+    // Index in objects, whose motion moves the light
+    object: u32,
+};
+
+struct DispatchArgs {
+    x: u32,
+    y: u32,
+    z: u32,
+};
+
+@group(2) @binding(0) var<storage, read_write> paths: array<PathState>;
+@group(2) @binding(1) var<storage, read_write> counters: Counters;
+// [rays (2 * path count), material queues (3 * path count), shadow rays (path count)]
+@group(2) @binding(2) var<storage, read_write> queues: array<u32>;
+@group(2) @binding(3) var<storage, read> lights: array<Light>;
+
+@group(3) @binding(0) var<storage, read_write> dispatch_args: array<DispatchArgs>;
+
+fn path_count() -> u32 {
+    return frame_data.width * frame_data.height;
+}
+
+fn ray_queue_offset(parity: u32) -> u32 {
+    return parity * path_count();
+}
+
+fn material_queue_offset(queue: u32) -> u32 {
+    return (2u + queue) * path_count();
+}
+
+fn shadow_queue_offset() -> u32 {
+    return 5u * path_count();
+}
+
+fn path_pixel(path_index: u32) -> vec2<u32> {
+    return vec2(path_index % frame_data.width, path_index / frame_data.width);
+}
+
+fn workgroups(count: u32) -> DispatchArgs {
+    return DispatchArgs((count + WF_WORKGROUP_SIZE - 1u) / WF_WORKGROUP_SIZE, 1u, 1u);
+}
+
+@compute @workgroup_size(1)
+fn wf_reset() {
+    atomicStore(&counters.rays, 0u);
+    atomicStore(&counters.next_rays, 0u);
+    atomicStore(&counters.shadow_rays, 0u);
+    counters.parity = 0u;
+    for (var m = 0u; m < 3u; m += 1u) {
+        atomicStore(&counters.materials[m], 0u);
+    }
+}
+
+@compute @workgroup_size(1)
+fn wf_dispatch_args() {
+    dispatch_args[0] = workgroups(atomicLoad(&counters.rays));
+    for (var m = 0u; m < 3u; m += 1u) {
+        dispatch_args[1u + m] = workgroups(atomicLoad(&counters.materials[m]));
+    }
+    dispatch_args[4] = workgroups(atomicLoad(&counters.shadow_rays));
+}
+
+@compute @workgroup_size(8, 8)
+fn wf_generate(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= frame_data.width || id.y >= frame_data.height {
+        return;
+    }
+    let i = id.y * frame_data.width + id.x;
+    var path = paths[i];
+
+    var flags = PATH_COUNT_EMITTED;
+    if path.frame != frame_data.frame_idx {
+        path.frame = frame_data.frame_idx;
+        path.sample_index = pixel_stats[i].samples;
+        path.frame_sample = 0u;
+    } else {
+        path.sample_index += 1u;
+        path.frame_sample += 1u;
+    }
+
+    if path.frame_sample >= pixel_stats[i].frame_samples {
+        path.flags = flags;
+        paths[i] = path;
+        return;
+    }
+    flags |= PATH_ACTIVE;
+    init_sampler(id.xy, path.sample_index);
+
+    var rngState = init_rng(
+        id.xy,
+        vec2<u32>(frame_data.width, frame_data.height),
+        path.sample_index,
+        render_param.seed
+    );
+    let film_sample = sample_filter(sample_2d(&rngState, DIM_PIXEL));
+    let ray = get_ray(&rngState, vec2<f32>(id.xy) + 0.5 + film_sample.offset);
+
+    path.origin = ray.origin;
+    path.direction = ray.direction;
+    path.time = ray.time;
+    path.throughput = vec3(1.0);
+    path.radiance = vec3(0.0);
+    path.filter_weight = film_sample.weight;
+    path.depth = 0u;
+    path.flags = flags;
+    path.rng = rngState;
+    paths[i] = path;
+
+    // Outside of the image the sample stays black
+    if !is_camera_ray(ray) {
+        return;
+    }
+    let slot = atomicAdd(&counters.rays, 1u);
+    queues[ray_queue_offset(counters.parity) + slot] = i;
+}
+
+@compute @workgroup_size(64)
+fn wf_intersect(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= atomicLoad(&counters.rays) {
+        return;
+    }
+    let path_index = queues[ray_queue_offset(counters.parity) + id.x];
+    var path = paths[path_index];
+
+    var hit = HitRecord();
+    if !check_intersection(Ray(path.origin, path.direction, path.time), &hit) {
+        // The sky is black, the path is done
+        return;
+    }
+
+    let material = materials[hit.material_index];
+    if material.id == MAT_DIFFUSE_LIGHT {
+        if (path.flags & PATH_COUNT_EMITTED) != 0u {
+            let emitted = path.throughput * texture_look_up(material.desc, 0.5, 0.5);
+            path.radiance += clamp_contribution(emitted, path.depth);
+            paths[path_index] = path;
+        }
+        return;
+    }
+    if material.id > QUEUE_DIELECTRIC {
+        return;
+    }
+
+    path.hit_p = hit.p;
+    path.hit_t = hit.t;
+    path.hit_normal = hit.normal;
+    path.hit_material = hit.material_index;
+    path.hit_front_face = select(0u, 1u, hit.front_face);
+    paths[path_index] = path;
+
+    let slot = atomicAdd(&counters.materials[material.id], 1u);
+    queues[material_queue_offset(material.id) + slot] = path_index;
+}
+
+// Picks a point on a light for next event estimation and queues the shadow ray.
+// Returns false when there is no light to sample.
+fn sample_light(
+    hit: HitRecord,
+    albedo: vec3<f32>,
+    path_index: u32,
+    path: ptr<function, PathState>,
+    rngState: ptr<function, u32>,
+) -> bool {
+    let light_count = arrayLength(&lights);
+    let pick = sample_1d(rngState, DIM_LIGHT_PICK);
+    let light = lights[min(u32(pick * f32(light_count)), light_count - 1u)];
+    if light.area <= 0.0 {
+        return false;
+    }
+
+    var point: vec3<f32>;
+    var light_normal: vec3<f32>;
+    let time = (*path).time;
+    if light.kind == OBJECT_SPHERE {
+        let sphere = spheres[light.index];
+        light_normal = square_to_sphere(sample_2d(rngState, DIM_LIGHT_POINT));
+        point = sphere_center(sphere, time) + sphere.radius * light_normal;
+    } else {
+        let surface = surfaces[light.index];
+        let b = square_to_triangle(sample_2d(rngState, DIM_LIGHT_POINT));
+        let e1 = surface.vertices[1].xyz - surface.vertices[0].xyz;
+        let e2 = surface.vertices[2].xyz - surface.vertices[0].xyz;
+        point = surface.vertices[0].xyz + b.x * e1 + b.y * e2;
+        light_normal = normalize(cross(e1, e2));
+    }
+    let object = objects[light.object];
+    if object.moving != 0u {
+        let transform = object_transform(object, time);
+        point = transform_point(transform, point);
+        light_normal = transform_vector(transform, light_normal);
+    }
+
+    let to_light = point - hit.p;
+    let distance_squared = dot(to_light, to_light);
+    let distance = sqrt(distance_squared);
+    let direction = to_light / distance;
+
+    let cos_surface = dot(hit.normal, direction);
+    // Lights are two sided, as in the megakernel
+    let cos_light = abs(dot(light_normal, direction));
+    if cos_surface <= 0.0 || cos_light <= 0.0 {
+        return true;
+    }
+
+    let emitted = texture_look_up(materials[light.material_index].desc, 0.5, 0.5);
+    // Lambertian BRDF over the area pdf of picking this point on this light
+    let weight = cos_surface * cos_light / distance_squared * light.area * f32(light_count);
+    (*path).shadow_direction = direction;
+    (*path).shadow_distance = distance;
+    let contribution = (*path).throughput * albedo * FRAC_1_PI * emitted * weight;
+    (*path).shadow_contribution = clamp_contribution(contribution, (*path).depth + 1u);
+
+    let slot = atomicAdd(&counters.shadow_rays, 1u);
+    queues[shadow_queue_offset() + slot] = path_index;
+    return true;
+}
+
+fn shade_queued(queue: u32, index: u32) {
+    if index >= atomicLoad(&counters.materials[queue]) {
+        return;
+    }
+    let path_index = queues[material_queue_offset(queue) + index];
+    var path = paths[path_index];
+    var rngState = path.rng;
+    init_sampler(path_pixel(path_index), path.sample_index);
+    sampler_start_bounce(path.depth);
+
+    let hit = HitRecord(
+        path.hit_p,
+        path.hit_normal,
+        path.hit_t,
+        path.hit_material,
+        path.hit_front_face != 0u
+    );
+    let material = materials[hit.material_index];
+
+    path.flags |= PATH_COUNT_EMITTED;
+    if queue == QUEUE_LAMBERTIAN {
+        let albedo = texture_look_up(material.desc, 0.5, 0.5);
+        if sample_light(hit, albedo, path_index, &path, &rngState) {
+            path.flags &= ~PATH_COUNT_EMITTED;
+        }
+    }
+
+    let scattered = scatter(Ray(path.origin, path.direction, path.time), hit, material, &rngState);
+    path.throughput *= scattered.attenuation;
+    path.origin = scattered.ray.origin;
+    path.direction = scattered.ray.direction;
+    path.depth += 1u;
+    // Not folded into a single condition, naga evaluates calls in `||` eagerly
+    var survived = true;
+    if path.depth >= render_param.rr_min_depth {
+        survived = russian_roulette(&path.throughput, &rngState);
+    }
+    path.rng = rngState;
+    paths[path_index] = path;
+    if !survived {
+        return;
+    }
+
+    let slot = atomicAdd(&counters.next_rays, 1u);
+    queues[ray_queue_offset(1u - counters.parity) + slot] = path_index;
+}
+
+@compute @workgroup_size(64)
+fn wf_shade_lambertian(@builtin(global_invocation_id) id: vec3<u32>) {
+    shade_queued(QUEUE_LAMBERTIAN, id.x);
+}
+
+@compute @workgroup_size(64)
+fn wf_shade_metal(@builtin(global_invocation_id) id: vec3<u32>) {
+    shade_queued(QUEUE_METAL, id.x);
+}
+
+@compute @workgroup_size(64)
+fn wf_shade_dielectric(@builtin(global_invocation_id) id: vec3<u32>) {
+    shade_queued(QUEUE_DIELECTRIC, id.x);
+}
+
+@compute @workgroup_size(64)
+fn wf_shadow(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= atomicLoad(&counters.shadow_rays) {
+        return;
+    }
+    let path_index = queues[shadow_queue_offset() + id.x];
+    let path = paths[path_index];
+
+    var hit = HitRecord();
+    let ray = Ray(path.hit_p, path.shadow_direction, path.time);
+    if check_intersection(ray, &hit) && hit.t < path.shadow_distance - SHADOW_EPSILON {
+        return;
+    }
+    paths[path_index].radiance += path.shadow_contribution;
+}
+
+@compute @workgroup_size(1)
+fn wf_next_bounce() {
+    atomicStore(&counters.rays, atomicLoad(&counters.next_rays));
+    atomicStore(&counters.next_rays, 0u);
+    atomicStore(&counters.shadow_rays, 0u);
+    counters.parity = 1u - counters.parity;
+    for (var m = 0u; m < 3u; m += 1u) {
+        atomicStore(&counters.materials[m], 0u);
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn wf_accumulate(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= frame_data.width || id.y >= frame_data.height {
+        return;
+    }
+    let i = id.y * frame_data.width + id.x;
+    let path = paths[i];
+    if (path.flags & PATH_ACTIVE) == 0u {
+        return;
+    }
+
+    var stats = pixel_stats[i];
+    let sum = load_pixel(i);
+    let sample = camera.exposure * path.filter_weight * path.radiance;
+    let accepted = reject_outlier(sample, stats);
+    record_sample(&stats, sample);
+    store_pixel(i, sum + accepted, stats);
+}
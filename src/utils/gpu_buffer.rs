@@ -1,25 +1,24 @@
+use std::{num::NonZeroU64, ops::Range};
+
+use bytemuck::Pod;
 use wgpu::util::DeviceExt;
 
 // thx to https://github.com/Nelarius/weekend-raytracer-wgpu/blob/main/src/raytracer/gpu_buffer.rs
 
-pub struct UniformBuffer {
+/// A uniform buffer holding a single `T`, written to the GPU only when the value changes.
+pub struct UniformBuffer<T: Pod> {
     handle: wgpu::Buffer,
     binding_idx: u32,
     label: String,
+    data: T,
+    dirty: bool,
 }
 
-impl UniformBuffer {
-    #[allow(dead_code)]
-    pub fn new(
-        device: &wgpu::Device,
-        buffer_size: wgpu::BufferAddress,
-        binding_idx: u32,
-        label: Option<&str>,
-    ) -> Self {
-        let handle = device.create_buffer(&wgpu::BufferDescriptor {
-            size: buffer_size,
+impl<T: Pod> UniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, data: T, binding_idx: u32, label: Option<&str>) -> Self {
+        let handle = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            contents: bytemuck::bytes_of(&data),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
             label,
         });
 
@@ -27,30 +26,28 @@ impl UniformBuffer {
             handle,
             binding_idx,
             label: String::from(label.unwrap_or("")),
+            data,
+            dirty: false,
         }
     }
 
-    pub fn new_from_bytes(
-        device: &wgpu::Device,
-        bytes: &[u8],
-        binding_idx: u32,
-        label: Option<&str>,
-    ) -> Self {
-        let handle = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            contents: bytes,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            label,
-        });
+    pub fn get(&self) -> &T {
+        &self.data
+    }
 
-        Self {
-            handle,
-            binding_idx,
-            label: String::from(label.unwrap_or("")),
+    pub fn set(&mut self, data: T) {
+        if bytemuck::bytes_of(&self.data) != bytemuck::bytes_of(&data) {
+            self.data = data;
+            self.dirty = true;
         }
     }
 
-    pub fn handle(&self) -> &wgpu::Buffer {
-        &self.handle
+    /// Writes the value to the GPU if it changed since the last call.
+    pub fn sync(&mut self, queue: &wgpu::Queue) {
+        if self.dirty {
+            queue.write_buffer(&self.handle, 0, bytemuck::bytes_of(&self.data));
+            self.dirty = false;
+        }
     }
 
     pub fn layout(&self, visibility: wgpu::ShaderStages) -> wgpu::BindGroupLayoutEntry {
@@ -76,37 +73,187 @@ impl UniformBuffer {
     }
 }
 
-pub struct StorageBuffer {
+/// A storage buffer of `T` with a CPU copy of its contents.
+///
+/// Modifications are recorded as dirty element ranges and only those are uploaded by
+/// [`StorageBuffer::sync`]. When the contents outgrow the GPU allocation, the capacity
+/// is doubled and the buffer recreated, `sync` then returns `true` so that the bind
+/// groups referencing it are rebuilt. The binding only covers `len` elements so that
+/// `arrayLength` in the shader matches the CPU side.
+pub struct StorageBuffer<T: Pod> {
     handle: wgpu::Buffer,
     binding_idx: u32,
     label: String,
+    data: Vec<T>,
+    /// Number of elements visible to the shader.
+    len: usize,
+    dirty: DirtyRanges,
+    rebind: bool,
 }
 
-impl StorageBuffer {
-    pub fn new_from_bytes(
+impl<T: Pod> StorageBuffer<T> {
+    const ELEMENT_SIZE: wgpu::BufferAddress = std::mem::size_of::<T>() as wgpu::BufferAddress;
+
+    fn create_handle(
+        device: &wgpu::Device,
+        capacity: usize,
+        usage: wgpu::BufferUsages,
+        label: &str,
+    ) -> wgpu::Buffer {
+        debug_assert!(Self::ELEMENT_SIZE % wgpu::COPY_BUFFER_ALIGNMENT == 0);
+        device.create_buffer(&wgpu::BufferDescriptor {
+            // Zero sized bindings are not allowed, always keep room for one element
+            size: capacity.max(1) as wgpu::BufferAddress * Self::ELEMENT_SIZE,
+            usage: usage
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+            label: Some(label),
+        })
+    }
+
+    pub fn new(device: &wgpu::Device, data: &[T], binding_idx: u32, label: Option<&str>) -> Self {
+        let label = String::from(label.unwrap_or(""));
+        let handle = if data.is_empty() {
+            Self::create_handle(device, 0, wgpu::BufferUsages::empty(), &label)
+        } else {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                contents: bytemuck::cast_slice(data),
+                usage: wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+                label: Some(&label),
+            })
+        };
+
+        Self {
+            handle,
+            binding_idx,
+            label,
+            data: data.to_vec(),
+            len: data.len(),
+            dirty: DirtyRanges::default(),
+            rebind: false,
+        }
+    }
+
+    /// Creates a zero initialised buffer of `len` elements that is only written by shaders,
+    /// no CPU copy is kept.
+    pub fn new_zeroed(
         device: &wgpu::Device,
-        bytes: &[u8],
+        len: usize,
         binding_idx: u32,
         label: Option<&str>,
     ) -> Self {
-        let handle = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            contents: bytes,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            label,
-        });
+        Self::new_zeroed_with_usage(device, len, wgpu::BufferUsages::empty(), binding_idx, label)
+    }
 
+    /// Same as [`StorageBuffer::new_zeroed`] with extra usages, e.g. `INDIRECT`.
+    pub fn new_zeroed_with_usage(
+        device: &wgpu::Device,
+        len: usize,
+        usage: wgpu::BufferUsages,
+        binding_idx: u32,
+        label: Option<&str>,
+    ) -> Self {
+        let label = String::from(label.unwrap_or(""));
         Self {
-            handle,
+            handle: Self::create_handle(device, len, usage, &label),
             binding_idx,
-            label: String::from(label.unwrap_or("")),
+            label,
+            data: Vec::new(),
+            len,
+            dirty: DirtyRanges::default(),
+            rebind: false,
         }
     }
 
-    #[allow(dead_code)]
     pub fn handle(&self) -> &wgpu::Buffer {
         &self.handle
     }
 
+    /// Copies the `len` visible elements back from the GPU, blocking until they arrive.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_back(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<T> {
+        let size = self.len as wgpu::BufferAddress * Self::ELEMENT_SIZE;
+        if size == 0 {
+            return Vec::new();
+        }
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} read back", self.label)),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("read back encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.handle, 0, &staging, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = staging.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            if let Err(error) = result {
+                log::error!("Failed to map the read back buffer: {}", error);
+            }
+        });
+        device.poll(wgpu::Maintain::Wait);
+        let data = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        staging.unmap();
+        data
+    }
+
+    /// Writes `data` straight to the GPU, for the buffers created by
+    /// [`StorageBuffer::new_zeroed`] that keep no CPU copy.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_unmirrored(&self, queue: &wgpu::Queue, data: &[T]) {
+        debug_assert!(self.data.is_empty() && data.len() <= self.len);
+        queue.write_buffer(&self.handle, 0, bytemuck::cast_slice(data));
+    }
+
+    fn capacity(&self) -> usize {
+        (self.handle.size() / Self::ELEMENT_SIZE) as usize
+    }
+
+    fn set_len(&mut self, len: usize) {
+        if len != self.len {
+            self.len = len;
+            self.rebind = true;
+        }
+    }
+
+    /// Replaces the contents with `data`, only the elements that differ are marked dirty.
+    pub fn replace(&mut self, data: &[T]) {
+        self.dirty.mark_changes(&self.data, data);
+        self.data.clear();
+        self.data.extend_from_slice(data);
+        self.set_len(data.len());
+    }
+
+    /// Uploads the dirty ranges, growing the GPU allocation if needed.
+    /// Returns `true` when the bind groups referencing this buffer must be rebuilt.
+    pub fn sync(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        if self.data.len() > self.capacity() {
+            let capacity = grown_capacity(self.capacity(), self.data.len());
+            log::debug!("{}: growing to {} elements", self.label, capacity);
+            self.handle = Self::create_handle(device, capacity, self.handle.usage(), &self.label);
+            self.dirty.clear();
+            self.dirty.mark(0..self.data.len());
+            self.rebind = true;
+        }
+
+        for range in self.dirty.drain() {
+            queue.write_buffer(
+                &self.handle,
+                range.start as wgpu::BufferAddress * Self::ELEMENT_SIZE,
+                bytemuck::cast_slice(&self.data[range]),
+            );
+        }
+
+        std::mem::take(&mut self.rebind)
+    }
+
     pub fn layout(
         &self,
         visibility: wgpu::ShaderStages,
@@ -127,9 +274,146 @@ impl StorageBuffer {
     pub fn binding(&self) -> wgpu::BindGroupEntry<'_> {
         let e = wgpu::BindGroupEntry {
             binding: self.binding_idx,
-            resource: self.handle.as_entire_binding(),
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &self.handle,
+                offset: 0,
+                size: NonZeroU64::new(self.len.max(1) as u64 * Self::ELEMENT_SIZE),
+            }),
         };
         log::debug!("{}: {:?}", self.label, e);
         e
     }
 }
+
+/// Element ranges of a buffer changed since its last upload. Overlapping or adjacent
+/// ranges are merged, so that each element is written once.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct DirtyRanges(Vec<Range<usize>>);
+
+impl DirtyRanges {
+    fn mark(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let mut merged = range;
+        self.0.retain(|r| {
+            if r.start <= merged.end && merged.start <= r.end {
+                merged = merged.start.min(r.start)..merged.end.max(r.end);
+                false
+            } else {
+                true
+            }
+        });
+        self.0.push(merged);
+    }
+
+    /// Marks the runs of elements of `new` that differ from `old`, and the ones past
+    /// its end.
+    fn mark_changes<T: Pod>(&mut self, old: &[T], new: &[T]) {
+        let common = old.len().min(new.len());
+        let mut run_start = None;
+        for (i, (old, new)) in old.iter().zip(new).enumerate() {
+            let changed = bytemuck::bytes_of(old) != bytemuck::bytes_of(new);
+            match (changed, run_start) {
+                (true, None) => run_start = Some(i),
+                (false, Some(start)) => {
+                    self.mark(start..i);
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = run_start {
+            self.mark(start..common);
+        }
+        self.mark(common..new.len());
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn drain(&mut self) -> std::vec::Drain<'_, Range<usize>> {
+        self.0.drain(..)
+    }
+}
+
+/// Capacity doubled from `capacity` until it holds `len` elements.
+fn grown_capacity(capacity: usize, len: usize) -> usize {
+    let mut capacity = capacity.max(1);
+    while capacity < len {
+        capacity *= 2;
+    }
+    capacity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ranges as `(start, end)` pairs in order.
+    fn sorted(dirty: &DirtyRanges) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<_> = dirty
+            .0
+            .iter()
+            .map(|range| (range.start, range.end))
+            .collect();
+        ranges.sort();
+        ranges
+    }
+
+    #[test]
+    fn dirty_ranges_merge() {
+        let mut dirty = DirtyRanges::default();
+        dirty.mark(4..6);
+        dirty.mark(10..12);
+        dirty.mark(3..3);
+        assert_eq!(sorted(&dirty), [(4, 6), (10, 12)]);
+
+        // Overlapping and adjacent ranges become one
+        dirty.mark(5..8);
+        assert_eq!(sorted(&dirty), [(4, 8), (10, 12)]);
+        dirty.mark(8..10);
+        assert_eq!(sorted(&dirty), [(4, 12)]);
+        dirty.mark(0..2);
+        dirty.mark(1..20);
+        assert_eq!(sorted(&dirty), [(0, 20)]);
+
+        assert_eq!(dirty.drain().collect::<Vec<_>>(), vec![0..20]);
+        assert_eq!(dirty, DirtyRanges::default());
+    }
+
+    #[test]
+    fn dirty_ranges_follow_changes() {
+        let mut dirty = DirtyRanges::default();
+        dirty.mark_changes(&[1_u32, 2, 3, 4, 5], &[1, 0, 0, 4, 0]);
+        assert_eq!(sorted(&dirty), [(1, 3), (4, 5)]);
+
+        // Appended elements are dirty, removed ones are not
+        let mut dirty = DirtyRanges::default();
+        dirty.mark_changes(&[1_u32, 2], &[1, 2, 3, 4]);
+        assert_eq!(sorted(&dirty), [(2, 4)]);
+        let mut dirty = DirtyRanges::default();
+        dirty.mark_changes(&[1_u32, 2, 3, 4], &[1, 0]);
+        assert_eq!(sorted(&dirty), [(1, 2)]);
+
+        // A change at the end of the common part joins the appended elements
+        let mut dirty = DirtyRanges::default();
+        dirty.mark_changes(&[1_u32, 2], &[1, 0, 3]);
+        assert_eq!(sorted(&dirty), [(1, 3)]);
+
+        let mut dirty = DirtyRanges::default();
+        dirty.mark_changes(&[1_u32, 2, 3], &[1, 2, 3]);
+        assert_eq!(dirty, DirtyRanges::default());
+    }
+
+    #[test]
+    fn capacity_doubles() {
+        assert_eq!(grown_capacity(0, 1), 1);
+        assert_eq!(grown_capacity(0, 5), 8);
+        assert_eq!(grown_capacity(4, 5), 8);
+        assert_eq!(grown_capacity(3, 7), 12);
+        assert_eq!(grown_capacity(16, 16), 16);
+        assert_eq!(grown_capacity(16, 33), 64);
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+use bytemuck::Pod;
+
+enum ReadbackState {
+    Idle,
+    /// The copy is recorded in the current frame.
+    Recorded,
+    /// Waiting for the buffer to be mapped, holds one of the `MAP_*` values.
+    Mapping(Arc<AtomicU8>),
+}
+
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+/// Reads a `T` back from a GPU buffer without stalling.
+///
+/// Only one copy is in flight at a time, the copies requested before the previous
+/// one was read are skipped.
+pub struct GpuReadback<T: Pod> {
+    buffer: wgpu::Buffer,
+    state: ReadbackState,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> GpuReadback<T> {
+    const SIZE: wgpu::BufferAddress = std::mem::size_of::<T>() as wgpu::BufferAddress;
+
+    pub fn new(device: &wgpu::Device, label: Option<&str>) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            state: ReadbackState::Idle,
+            _marker: PhantomData,
+        }
+    }
+
+    /// `false` while a previous copy is still in flight.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.state, ReadbackState::Idle)
+    }
+
+    /// Copies the start of `source` for reading, skipped unless [`GpuReadback::is_idle`].
+    pub fn copy(&mut self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer) {
+        if !self.is_idle() {
+            return;
+        }
+        encoder.copy_buffer_to_buffer(source, 0, &self.buffer, 0, Self::SIZE);
+        self.state = ReadbackState::Recorded;
+    }
+
+    /// Starts mapping the copy, to call once the frame is submitted.
+    pub fn after_submit(&mut self) {
+        if !matches!(self.state, ReadbackState::Recorded) {
+            return;
+        }
+        let mapped = Arc::new(AtomicU8::new(MAP_PENDING));
+        let on_mapped = mapped.clone();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let state = if result.is_ok() { MAP_DONE } else { MAP_FAILED };
+                on_mapped.store(state, Ordering::Release);
+            });
+        self.state = ReadbackState::Mapping(mapped);
+    }
+
+    /// The copied value, once it is available.
+    pub fn read(&mut self, device: &wgpu::Device) -> Option<T> {
+        let ReadbackState::Mapping(mapped) = &self.state else {
+            return None;
+        };
+        device.poll(wgpu::Maintain::Poll);
+        match mapped.load(Ordering::Acquire) {
+            MAP_PENDING => return None,
+            MAP_FAILED => {
+                self.state = ReadbackState::Idle;
+                return None;
+            }
+            _ => {}
+        }
+
+        let value = {
+            let view = self.buffer.slice(..).get_mapped_range();
+            bytemuck::pod_read_unaligned(&view)
+        };
+        self.buffer.unmap();
+        self.state = ReadbackState::Idle;
+        Some(value)
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use super::GpuReadback;
+
+/// Measures the GPU duration of a pass with timestamp queries.
+///
+/// The result is read back asynchronously, a new measurement only starts once the
+/// previous one was read, the passes recorded in between are not timed.
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback: GpuReadback<[u64; 2]>,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+impl GpuTimer {
+    const SIZE: wgpu::BufferAddress = 2 * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+    /// Returns `None` when the device was created without [`wgpu::Features::TIMESTAMP_QUERY`].
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("gpu timer queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu timer resolve buffer"),
+            size: Self::SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback: GpuReadback::new(device, Some("gpu timer readback buffer")),
+            period: queue.get_timestamp_period(),
+        })
+    }
+
+    /// Timestamps to write at the beginning and end of the timed compute pass, `None`
+    /// while the previous measurement is still in flight.
+    pub fn compute_pass_writes(&self) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        self.readback
+            .is_idle()
+            .then_some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index: Some(0),
+                end_of_pass_write_index: Some(1),
+            })
+    }
+
+    /// Copies the timestamps written by the pass for reading, must be recorded after the
+    /// pass using [`GpuTimer::compute_pass_writes`].
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.readback.is_idle() {
+            return;
+        }
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        self.readback.copy(encoder, &self.resolve_buffer);
+    }
+
+    /// Starts reading the timestamps back, to call once the frame is submitted.
+    pub fn after_submit(&mut self) {
+        self.readback.after_submit();
+    }
+
+    /// Duration of the last timed pass, once it is available.
+    pub fn read(&mut self, device: &wgpu::Device) -> Option<Duration> {
+        let timestamps = self.readback.read(device)?;
+        let ticks = timestamps[1].saturating_sub(timestamps[0]);
+        Some(Duration::from_nanos(
+            (ticks as f64 * self.period as f64) as u64,
+        ))
+    }
+}
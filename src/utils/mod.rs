@@ -1,7 +1,15 @@
 mod egui_tools;
 mod gpu_buffer;
+mod gpu_readback;
+mod gpu_timer;
+#[cfg(not(target_arch = "wasm32"))]
+mod rng;
 pub use egui_tools::EguiRenderer;
 pub use gpu_buffer::{StorageBuffer, UniformBuffer};
+pub use gpu_readback::GpuReadback;
+pub use gpu_timer::GpuTimer;
+#[cfg(not(target_arch = "wasm32"))]
+pub use rng::Rng;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
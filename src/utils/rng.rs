@@ -0,0 +1,242 @@
+// CPU mirror of shader/rng.wgsl, producing the same sequences as the shader for a
+// given state. The generator places the procedural scenes, the mappings to the sampled
+// shapes are mirrored to test their distributions.
+
+#[cfg(test)]
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+pub fn jenkin_hash(input: u32) -> u32 {
+    let mut x = input;
+    x = x.wrapping_add(x << 10);
+    x ^= x >> 6;
+    x = x.wrapping_add(x << 3);
+    x ^= x >> 11;
+    x = x.wrapping_add(x << 15);
+    x
+}
+
+/// PCG random number generator, as `rng_next_int` in the shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(state: u32) -> Self {
+        Self { state }
+    }
+
+    /// Generator for procedural scene content, the same `seed` giving the same scene.
+    pub fn from_seed(seed: u32) -> Self {
+        Self::new(jenkin_hash(seed))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state = self.state.wrapping_mul(747796405).wrapping_add(2891336453);
+        let word = ((self.state >> ((self.state >> 28) + 4)) ^ self.state).wrapping_mul(277803737);
+        (word >> 22) ^ word
+    }
+
+    /// Uniform float in [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        u32_to_unit_float(self.next_u32())
+    }
+}
+
+#[cfg(test)]
+impl Rng {
+    /// Same state as `init_rng` in the shader.
+    pub fn for_pixel(pixel: (u32, u32), resolution: (u32, u32), sample: u32, seed: u32) -> Self {
+        let pixel_seed = pixel.0.wrapping_add(pixel.1.wrapping_mul(resolution.0))
+            ^ jenkin_hash(sample ^ jenkin_hash(seed));
+        Self::new(jenkin_hash(pixel_seed))
+    }
+
+    pub fn next_vec2(&mut self) -> glm::Vec2 {
+        let x = self.next_f32();
+        glm::vec2(x, self.next_f32())
+    }
+}
+
+pub fn u32_to_unit_float(x: u32) -> f32 {
+    // Keep 24 bits so that the result stays below 1
+    (x >> 8) as f32 * (1.0 / 16777216.0)
+}
+
+/// Concentric mapping of the unit square to the unit disk.
+#[cfg(test)]
+pub fn square_to_disk(u: glm::Vec2) -> glm::Vec2 {
+    let offset = u * 2.0 - glm::vec2(1.0, 1.0);
+    if offset.x == 0.0 && offset.y == 0.0 {
+        return glm::vec2(0.0, 0.0);
+    }
+    if offset.x.abs() > offset.y.abs() {
+        let theta = FRAC_PI_4 * (offset.y / offset.x);
+        return glm::vec2(theta.cos(), theta.sin()) * offset.x;
+    }
+    let theta = FRAC_PI_2 - FRAC_PI_4 * (offset.x / offset.y);
+    glm::vec2(theta.cos(), theta.sin()) * offset.y
+}
+
+/// Uniform direction, i.e. a point on the unit sphere.
+#[cfg(test)]
+pub fn square_to_sphere(u: glm::Vec2) -> glm::Vec3 {
+    let z = 1.0 - 2.0 * u.y;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.x;
+    glm::vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Uniform direction around +z.
+#[cfg(test)]
+pub fn square_to_hemisphere(u: glm::Vec2) -> glm::Vec3 {
+    let z = u.y;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * u.x;
+    glm::vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// Cosine weighted direction around +z.
+#[cfg(test)]
+pub fn square_to_cosine_hemisphere(u: glm::Vec2) -> glm::Vec3 {
+    let z = (1.0 - u.y).sqrt();
+    let phi = 2.0 * PI * u.x;
+    let r = u.y.sqrt();
+    glm::vec3(phi.cos() * r, phi.sin() * r, z)
+}
+
+/// Uniform barycentric coordinates `(b1, b2)` of a point in a triangle.
+#[cfg(test)]
+pub fn square_to_triangle(u: glm::Vec2) -> glm::Vec2 {
+    let su = u.x.sqrt();
+    glm::vec2(su * (1.0 - u.y), su * u.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: u32 = 100_000;
+
+    /// Mean of `f` over `SAMPLES` draws of the generator.
+    fn mean(mut f: impl FnMut(&mut Rng) -> f32) -> f32 {
+        let mut rng = Rng::from_seed(7);
+        let sum: f64 = (0..SAMPLES).map(|_| f(&mut rng) as f64).sum();
+        (sum / SAMPLES as f64) as f32
+    }
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < 0.01,
+            "{} instead of {}",
+            value,
+            expected
+        );
+    }
+
+    fn assert_unit(p: glm::Vec3) {
+        assert!(
+            (p.magnitude() - 1.0).abs() < 1e-5,
+            "{:?} is not a direction",
+            p
+        );
+    }
+
+    #[test]
+    fn unit_float_stays_below_one() {
+        assert_eq!(u32_to_unit_float(0), 0.0);
+        assert!(u32_to_unit_float(u32::MAX) < 1.0);
+
+        let x = mean(|rng| {
+            let x = rng.next_f32();
+            assert!((0.0..1.0).contains(&x));
+            x
+        });
+        assert_close(x, 0.5);
+        assert_close(mean(|rng| rng.next_f32().powi(2)), 1.0 / 3.0);
+        assert_close(mean(|rng| rng.next_f32() * rng.next_f32()), 0.25);
+    }
+
+    #[test]
+    fn pixel_state_only_depends_on_sample_and_seed() {
+        let state = |sample, seed| Rng::for_pixel((3, 5), (16, 9), sample, seed).next_u32();
+        assert_eq!(state(4, 1), state(4, 1));
+        assert_ne!(state(4, 1), state(5, 1));
+        assert_ne!(state(4, 1), state(4, 2));
+    }
+
+    #[test]
+    fn disk() {
+        let disk = |rng: &mut Rng| square_to_disk(rng.next_vec2());
+        let r2 = mean(|rng| {
+            let p = disk(rng);
+            assert!(p.magnitude() <= 1.0 + 1e-6);
+            p.magnitude_squared()
+        });
+        assert_close(r2, 0.5);
+        assert_close(mean(|rng| disk(rng).x), 0.0);
+        assert_close(mean(|rng| disk(rng).y), 0.0);
+        // The same area on each side of the diagonal, unlike a square stretched to the disk
+        assert_close(mean(|rng| disk(rng).x.powi(2)), 0.25);
+        assert_close(mean(|rng| disk(rng).y.powi(2)), 0.25);
+    }
+
+    #[test]
+    fn sphere() {
+        let sphere = |rng: &mut Rng| square_to_sphere(rng.next_vec2());
+        let z = mean(|rng| {
+            let p = sphere(rng);
+            assert_unit(p);
+            p.z
+        });
+        assert_close(z, 0.0);
+        assert_close(mean(|rng| sphere(rng).x), 0.0);
+        assert_close(mean(|rng| sphere(rng).y), 0.0);
+        for axis in 0..3 {
+            assert_close(mean(|rng| sphere(rng)[axis].powi(2)), 1.0 / 3.0);
+        }
+    }
+
+    #[test]
+    fn hemisphere() {
+        let hemisphere = |rng: &mut Rng| square_to_hemisphere(rng.next_vec2());
+        let z = mean(|rng| {
+            let p = hemisphere(rng);
+            assert_unit(p);
+            assert!(p.z >= 0.0);
+            p.z
+        });
+        assert_close(z, 0.5);
+        assert_close(mean(|rng| hemisphere(rng).z.powi(2)), 1.0 / 3.0);
+        assert_close(mean(|rng| hemisphere(rng).x), 0.0);
+        assert_close(mean(|rng| hemisphere(rng).y), 0.0);
+    }
+
+    #[test]
+    fn cosine_hemisphere() {
+        let hemisphere = |rng: &mut Rng| square_to_cosine_hemisphere(rng.next_vec2());
+        let z = mean(|rng| {
+            let p = hemisphere(rng);
+            assert_unit(p);
+            assert!(p.z >= 0.0);
+            p.z
+        });
+        assert_close(z, 2.0 / 3.0);
+        assert_close(mean(|rng| hemisphere(rng).z.powi(2)), 0.5);
+        assert_close(mean(|rng| hemisphere(rng).x), 0.0);
+        assert_close(mean(|rng| hemisphere(rng).y), 0.0);
+    }
+
+    #[test]
+    fn triangle() {
+        let triangle = |rng: &mut Rng| square_to_triangle(rng.next_vec2());
+        let b1 = mean(|rng| {
+            let b = triangle(rng);
+            assert!(b.x >= 0.0 && b.y >= 0.0 && b.x + b.y <= 1.0 + 1e-6);
+            b.x
+        });
+        assert_close(b1, 1.0 / 3.0);
+        assert_close(mean(|rng| triangle(rng).y), 1.0 / 3.0);
+        assert_close(mean(|rng| triangle(rng).x.powi(2)), 1.0 / 6.0);
+    }
+}